@@ -0,0 +1,233 @@
+use crate::*;
+
+/// batches for accounts with many listings and offers: one ft_transfer_call (msg
+/// BatchOfferArgs) or batch_offer_by_near spreads its deposit over several offers, and
+/// cancel_loans / cancel_offers cancel many at once. Items are checked one by one, an item
+/// that fails or no longer fits in the gas is skipped, and the principal of skipped offers
+/// is refunded
+
+/// gas kept aside for each offer of a batch
+const GAS_PER_BATCH_OFFER: Gas = 5_000_000_000_000;
+/// refund of a cancelled offer
+const GAS_PER_OFFER_CANCEL: Gas = GAS_FOR_FT_TRANSFER + 5_000_000_000_000;
+/// cancelled listing and the return of an escrowed NFT, the refunds of its open offers
+/// (GAS_PER_OFFER_REFUND each) come on top
+const GAS_PER_LOAN_CANCEL: Gas = GAS_FOR_NFT_TRANSFER + 5_000_000_000_000;
+
+/// one "offer" of a batch, the fields of PurchaseArgs it uses
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BatchOffer {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub loan_principal_amount: U128,
+    pub loan_duration: u32,
+    pub loan_interest_rate: u32,
+    pub available_at: u64,
+    pub renewable: Option<bool>,
+    pub min_on_time_rate: Option<u32>,
+    pub referrer_id: Option<AccountId>,
+    pub variable_rate: Option<bool>,
+    pub allowed_borrower: Option<AccountId>,
+}
+
+/// ft_transfer_call msg, the transferred amount must cover the principal of every offer
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BatchOfferArgs {
+    pub offers: Vec<BatchOffer>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OfferRef {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub offer_id: u32,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// returns the refunded part of the deposit
+    #[payable]
+    pub fn batch_offer_by_near(&mut self, offers: Vec<BatchOffer>) -> U128 {
+        let lender_id = user_account_id();
+        let refund = self.internal_batch_offer(
+            &lender_id,
+            &"near".to_string(),
+            env::attached_deposit(),
+            offers,
+        );
+        if refund > 0 {
+            Promise::new(lender_id).transfer(refund);
+        }
+        U128(refund)
+    }
+
+    /// borrower cancels several open listings, skips the ones it cannot cancel
+    pub fn cancel_loans(&mut self, nft_contract_tokens: Vec<ContractAndTokenId>) -> Vec<bool> {
+        let owner_id = user_account_id();
+        let mut canceled = vec![];
+        for contract_and_token_id in nft_contract_tokens {
+            match self.sales.get(&contract_and_token_id) {
+                Some(sale)
+                    if sale.owner_id == owner_id && sale.status == LoanStatus::Open as u32 =>
+                {
+                    // the open offers of a listing are refunded with it
+                    let open_offers = self
+                        .internal_offers(&contract_and_token_id)
+                        .iter()
+                        .filter(|offer| offer.status == LoanStatus::Open as u32)
+                        .count() as u64;
+                    if env::prepaid_gas() - env::used_gas()
+                        < GAS_PER_LOAN_CANCEL + open_offers * GAS_PER_OFFER_REFUND
+                    {
+                        canceled.push(false);
+                        continue;
+                    }
+                    self.internal_cancel_sale(&contract_and_token_id, sale);
+                    canceled.push(true);
+                }
+                _ => canceled.push(false),
+            }
+        }
+        canceled
+    }
+
+    /// lender cancels several open offers, skips the ones it cannot cancel
+    pub fn cancel_offers(&mut self, offers: Vec<OfferRef>) -> Vec<bool> {
+        let lender_id = user_account_id();
+        let mut canceled = vec![];
+        for OfferRef {
+            nft_contract_id,
+            token_id,
+            offer_id,
+        } in offers
+        {
+            if env::prepaid_gas() - env::used_gas() < GAS_PER_OFFER_CANCEL {
+                canceled.push(false);
+                continue;
+            }
+            let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+            let sale = match self.sales.get(&contract_and_token_id) {
+                Some(sale) => sale,
+                None => {
+                    canceled.push(false);
+                    continue;
+                }
+            };
+            match self.internal_get_offer(&contract_and_token_id, offer_id) {
+                Some(offer)
+                    if offer.lender_id == lender_id && offer.status == LoanStatus::Open as u32 =>
+                {
+                    self.internal_cancel_offer(&contract_and_token_id, &sale, offer);
+                    canceled.push(true);
+                }
+                _ => canceled.push(false),
+            }
+        }
+        canceled
+    }
+}
+
+impl Contract {
+    /// places each offer that passes its checks, returns what is left of `amount`
+    pub(crate) fn internal_batch_offer(
+        &mut self,
+        lender_id: &AccountId,
+        ft_token_id: &AccountId,
+        amount: Balance,
+        offers: Vec<BatchOffer>,
+    ) -> Balance {
+        let mut remaining = amount;
+        for item in offers {
+            let contract_and_token_id =
+                format!("{}{}{}", item.nft_contract_id, DELIMETER, item.token_id);
+            if env::prepaid_gas() - env::used_gas() < GAS_PER_BATCH_OFFER {
+                env::log(
+                    format!("Offer on {} skipped: out of gas", contract_and_token_id).as_bytes(),
+                );
+                continue;
+            }
+            match self.check_batch_offer(
+                &contract_and_token_id,
+                lender_id,
+                ft_token_id,
+                remaining,
+                item,
+            ) {
+                Ok(offer) => {
+                    remaining -= offer.loan_principal_amount;
+                    self.internal_add_offer(&contract_and_token_id, offer);
+                }
+                Err(message) => {
+                    env::log(
+                        format!("Offer on {} skipped: {}", contract_and_token_id, message)
+                            .as_bytes(),
+                    );
+                }
+            }
+        }
+        remaining
+    }
+
+    /// the checks of an "offer" action, without panicking
+    fn check_batch_offer(
+        &self,
+        contract_and_token_id: &ContractAndTokenId,
+        lender_id: &AccountId,
+        ft_token_id: &AccountId,
+        remaining: Balance,
+        item: BatchOffer,
+    ) -> Result<Offer, String> {
+        let sale = self
+            .sales
+            .get(contract_and_token_id)
+            .ok_or_else(|| "No sale".to_string())?;
+        if &sale.loan_currency != ft_token_id {
+            return Err("ft_token is invalid".to_string());
+        }
+        if sale.status != LoanStatus::Open as u32 {
+            return Err("invalid loan status".to_string());
+        }
+        if &sale.owner_id == lender_id {
+            return Err("Cannot buy your own sale.".to_string());
+        }
+        check_allowed_lender(&sale, lender_id)?;
+        check_referrers(&sale, lender_id, item.referrer_id.as_ref())?;
+        let amount = item.loan_principal_amount.0;
+        if amount == 0 || amount > remaining {
+            return Err(format!(
+                "Deposit left {} does not cover {}",
+                remaining, amount
+            ));
+        }
+        self.check_offer_terms(&sale, amount, item.loan_duration)?;
+        let variable_rate = item.variable_rate.unwrap_or(false);
+        if variable_rate {
+            self.check_variable_offer(&sale)?;
+        }
+        let now = U64(env::block_timestamp() / 1000000000);
+        let offer = Offer {
+            offer_id: 0,
+            lender_id: lender_id.clone(),
+            loan_principal_amount: amount,
+            loan_duration: item.loan_duration,
+            loan_interest_rate: item.loan_interest_rate,
+            created_at: now,
+            updated_at: now,
+            started_at: U64(0),
+            status: LoanStatus::Open as u32,
+            available_at: item.available_at,
+            renewable: item.renewable.unwrap_or(false),
+            min_on_time_rate: item.min_on_time_rate.unwrap_or(0),
+            referrer_id: item.referrer_id,
+            variable_rate,
+            rate_index: U128(0),
+            allowed_borrower: item.allowed_borrower,
+        };
+        check_allowed_borrower(&offer, &sale.owner_id)?;
+        self.check_new_offer(contract_and_token_id, &offer)?;
+        Ok(offer)
+    }
+}
@@ -0,0 +1,199 @@
+use crate::*;
+
+/// bundle collateral: further NFTs pledged on the same loan as a listed sale. The sale's own
+/// NFT leads the bundle, every other item is added with nft_approve and msg BundleArgs, all
+/// of them go into escrow when the loan starts and leave it together
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleItem {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub approval_id: u64,
+}
+
+/// nft_approve msg adding the token to the open sale of bundle_nft_contract_id/bundle_token_id
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleArgs {
+    pub bundle_nft_contract_id: AccountId,
+    pub bundle_token_id: TokenId,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// borrower takes an item out of the bundle of its open sale
+    pub fn remove_bundle_item(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        item_nft_contract_id: AccountId,
+        item_token_id: TokenId,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert_borrower(&sale);
+        assert!(
+            sale.status == LoanStatus::Open as u32,
+            "invalid loan status",
+        );
+        let mut items = self
+            .bundle_items
+            .get(&contract_and_token_id)
+            .unwrap_or_default();
+        let len = items.len();
+        items.retain(|item| {
+            item.nft_contract_id != item_nft_contract_id || item.token_id != item_token_id
+        });
+        assert!(items.len() < len, "No bundle item");
+        self.bundle_items.insert(&contract_and_token_id, &items);
+        self.bundled_in.remove(&format!(
+            "{}{}{}",
+            item_nft_contract_id, DELIMETER, item_token_id
+        ));
+    }
+
+    /// views
+
+    /// items pledged together with the sale's own NFT
+    pub fn get_bundle_items(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+    ) -> Vec<BundleItem> {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        self.bundle_items
+            .get(&contract_and_token_id)
+            .unwrap_or_default()
+    }
+
+    /// the sale an NFT is pledged in as a bundle item, if any
+    pub fn get_bundle_of(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+    ) -> Option<ContractAndTokenId> {
+        let item_key = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        self.internal_live_bundle_of(&item_key)
+    }
+}
+
+impl Contract {
+    /// adds an approved NFT to an open sale of the same owner, approving it again only
+    /// refreshes its approval_id
+    pub(crate) fn internal_add_bundle_item(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+        args: BundleArgs,
+    ) {
+        let contract_and_token_id = format!(
+            "{}{}{}",
+            args.bundle_nft_contract_id, DELIMETER, args.bundle_token_id
+        );
+        let item_key = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        assert!(
+            item_key != contract_and_token_id,
+            "A sale cannot bundle its own NFT"
+        );
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert!(
+            sale.owner_id == owner_id,
+            "invalid owner owner's loan:{}, signer:{}",
+            sale.owner_id,
+            owner_id,
+        );
+        assert!(
+            sale.status == LoanStatus::Open as u32,
+            "invalid loan status",
+        );
+        self.assert_not_pledged(&item_key);
+        if let Some(bundle_of) = self.internal_live_bundle_of(&item_key) {
+            assert!(
+                bundle_of == contract_and_token_id,
+                "NFT is already bundled in {}",
+                bundle_of
+            );
+        }
+
+        let mut items = self
+            .bundle_items
+            .get(&contract_and_token_id)
+            .unwrap_or_default();
+        items.retain(|item| item.nft_contract_id != nft_contract_id || item.token_id != token_id);
+        // items are small, the sale's STORAGE_PER_SALE covers MAX_BUNDLE_ITEMS of them
+        assert!(
+            items.len() < MAX_BUNDLE_ITEMS,
+            "A bundle holds at most {} items",
+            MAX_BUNDLE_ITEMS
+        );
+        items.push(BundleItem {
+            nft_contract_id,
+            token_id,
+            approval_id,
+        });
+        self.bundle_items.insert(&contract_and_token_id, &items);
+        self.bundled_in.insert(&item_key, &contract_and_token_id);
+    }
+
+    /// an NFT cannot be listed or bundled while it backs an open or running sale
+    pub(crate) fn assert_not_pledged(&self, item_key: &ContractAndTokenId) {
+        if let Some(sale) = self.sales.get(item_key) {
+            assert!(
+                sale.status != LoanStatus::Open as u32
+                    && sale.status != LoanStatus::Processing as u32,
+                "Loan is proccessing",
+            );
+        }
+    }
+
+    /// the open or running sale `item_key` is bundled in, entries of finished sales are stale
+    pub(crate) fn internal_live_bundle_of(
+        &self,
+        item_key: &ContractAndTokenId,
+    ) -> Option<ContractAndTokenId> {
+        let contract_and_token_id = self.bundled_in.get(item_key)?;
+        let sale = self.sales.get(&contract_and_token_id)?;
+        let live = sale.status == LoanStatus::Open as u32
+            || sale.status == LoanStatus::Processing as u32
+            || (sale.status == LoanStatus::Liquidated as u32
+                && sale.loan_config == LoanConfig::Tranched as u32);
+        let listed = self
+            .bundle_items
+            .get(&contract_and_token_id)
+            .unwrap_or_default()
+            .iter()
+            .any(|item| {
+                format!("{}{}{}", item.nft_contract_id, DELIMETER, item.token_id) == *item_key
+            });
+        if live && listed {
+            Some(contract_and_token_id)
+        } else {
+            None
+        }
+    }
+
+    /// drops the items of a finished listing before the NFT is listed again
+    pub(crate) fn internal_clear_bundle(&mut self, contract_and_token_id: &ContractAndTokenId) {
+        if let Some(items) = self.bundle_items.remove(contract_and_token_id) {
+            for item in items {
+                let item_key = format!("{}{}{}", item.nft_contract_id, DELIMETER, item.token_id);
+                if self.bundled_in.get(&item_key).as_ref() == Some(contract_and_token_id) {
+                    self.bundled_in.remove(&item_key);
+                }
+            }
+        }
+    }
+
+    /// extra gas moving the bundle items of a sale takes
+    pub(crate) fn internal_bundle_gas(&self, contract_and_token_id: &ContractAndTokenId) -> Gas {
+        let items = self
+            .bundle_items
+            .get(contract_and_token_id)
+            .map(|items| items.len())
+            .unwrap_or(0);
+        items as Gas * GAS_FOR_NFT_TRANSFER
+    }
+}
@@ -0,0 +1,154 @@
+use crate::*;
+use pawn_interest::{Accrual, InterestConfig, LoanTerms, Payoff};
+
+/// callable loans, see LoanConfig::Callable: no maturity, interest accrues per second until
+/// the lender (the note holder if a note was minted) calls the loan. The borrower then has
+/// loan_duration seconds of notice to pay back, after that the loan can be liquidated
+
+/// shortest notice, loan_duration, a callable listing or offer can give the borrower
+pub const MIN_CALL_NOTICE: u32 = 86400;
+
+/// payoff of a callable loan repaid at `pay_at`, interest for every second it ran and no
+/// early repayment charge
+pub(crate) fn callable_payoff(
+    loan_principal_amount: u128,
+    interest_rate: u32,
+    loan_started_at: u64,
+    pay_at: u64,
+    fee_bps: u32,
+) -> Payoff {
+    let terms = LoanTerms {
+        principal: loan_principal_amount,
+        duration: pay_at.saturating_sub(loan_started_at),
+        interest_rate,
+        started_at: loan_started_at,
+    };
+    InterestConfig {
+        accrual: Accrual::PerSecond,
+        early_repayment_bps: 0,
+        fee_bps,
+        ..InterestConfig::default()
+    }
+    .payoff(&terms, pay_at)
+    .expect("pay amount overflow")
+}
+
+/// when the loan is due (seconds), None for a callable loan that was not called
+pub(crate) fn loan_maturity(sale: &Sale) -> Option<u64> {
+    if sale.loan_config == LoanConfig::Callable as u32 {
+        if sale.called_at.0 == 0 {
+            None
+        } else {
+            Some(sale.called_at.0 + sale.loan_duration as u64)
+        }
+    } else {
+        Some(sale.started_at.0 + sale.loan_duration as u64)
+    }
+}
+
+/// last time the loan can be paid back, fixed-term loans get the grace period on top while
+/// the notice of a callable loan is its grace period
+pub(crate) fn loan_deadline(sale: &Sale) -> Option<u64> {
+    let maturity = loan_maturity(sale)?;
+    if sale.loan_config == LoanConfig::Callable as u32 {
+        Some(maturity)
+    } else {
+        Some(maturity + LOAN_GRACE_PERIOD as u64)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// starts the notice period of a callable loan
+    pub fn call_loan(&mut self, nft_contract_id: AccountId, token_id: String) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let offer = self.internal_callable_offer(&contract_and_token_id, &sale);
+        match sale.note_contract_id.clone() {
+            None => {
+                assert_lender(&offer);
+                self.internal_call_loan(&contract_and_token_id, sale);
+            }
+            Some(note_contract_id) => {
+                ext_contract::nft_token(
+                    note_token_id(
+                        &sale.nft_contract_id,
+                        &sale.token_id,
+                        sale.started_at.0,
+                        offer.offer_id,
+                    ),
+                    &note_contract_id,
+                    NO_DEPOSIT,
+                    GAS_FOR_NOTE,
+                )
+                .then(ext_self::resolve_call_loan(
+                    nft_contract_id,
+                    token_id,
+                    user_account_id(),
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_RESOLVE_CALL,
+                ));
+            }
+        }
+    }
+
+    /// `caller_id` must hold the note of the loan, the lender if no note was minted
+    #[private]
+    pub fn resolve_call_loan(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        caller_id: AccountId,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let offer = self.internal_callable_offer(&contract_and_token_id, &sale);
+        let holder_id = token_owner().unwrap_or(offer.lender_id);
+        assert!(
+            holder_id == caller_id,
+            "invalid lender loan's holder:{}, caller:{}",
+            holder_id,
+            caller_id,
+        );
+        self.internal_call_loan(&contract_and_token_id, sale);
+    }
+}
+
+impl Contract {
+    /// the funded offer of a running callable loan
+    fn internal_callable_offer(
+        &self,
+        contract_and_token_id: &ContractAndTokenId,
+        sale: &Sale,
+    ) -> Offer {
+        assert!(
+            sale.loan_config == LoanConfig::Callable as u32,
+            "Loan is not callable"
+        );
+        assert!(
+            sale.status == LoanStatus::Processing as u32,
+            "Loan is not in processing"
+        );
+        self.internal_offers(contract_and_token_id)
+            .into_iter()
+            .find(|offer| offer.status == LoanStatus::Processing as u32)
+            .expect("No offer")
+    }
+
+    fn internal_call_loan(&mut self, contract_and_token_id: &ContractAndTokenId, mut sale: Sale) {
+        assert!(sale.called_at.0 == 0, "Loan is already called");
+        let now = env::block_timestamp() / 1000000000;
+        sale.called_at = U64(now);
+        sale.updated_at = U64(now);
+        self.sales.insert(contract_and_token_id, &sale);
+        env::log(
+            format!(
+                "Loan {} called, due at {}",
+                contract_and_token_id,
+                now + sale.loan_duration as u64
+            )
+            .as_bytes(),
+        );
+    }
+}
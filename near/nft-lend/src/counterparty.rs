@@ -0,0 +1,70 @@
+use crate::*;
+
+/// private loans: a listing can name the only lenders allowed to fund it
+/// (SaleArgs.allowed_lenders) and an offer the only borrower allowed to accept it
+/// (PurchaseArgs.allowed_borrower). Neither list is serialized in sale or offer views,
+/// a counterparty checks itself with is_allowed_lender / is_allowed_borrower
+
+/// empty allowed_lenders means anyone
+pub(crate) fn check_allowed_lender(sale: &Sale, lender_id: &AccountId) -> Result<(), String> {
+    if sale.allowed_lenders.is_empty() || sale.allowed_lenders.contains(lender_id) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} is not an allowed lender of this sale",
+            lender_id
+        ))
+    }
+}
+
+pub(crate) fn check_allowed_borrower(offer: &Offer, borrower_id: &AccountId) -> Result<(), String> {
+    match &offer.allowed_borrower {
+        Some(allowed_borrower) if allowed_borrower != borrower_id => Err(format!(
+            "Offer {} is not open to {}",
+            offer.offer_id, borrower_id
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn assert_allowed_lender(sale: &Sale, lender_id: &AccountId) {
+    if let Err(message) = check_allowed_lender(sale, lender_id) {
+        env::panic(message.as_bytes());
+    }
+}
+
+pub(crate) fn assert_allowed_borrower(offer: &Offer, borrower_id: &AccountId) {
+    if let Err(message) = check_allowed_borrower(offer, borrower_id) {
+        env::panic(message.as_bytes());
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// views
+
+    pub fn is_allowed_lender(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        account_id: AccountId,
+    ) -> bool {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        check_allowed_lender(&sale, &account_id).is_ok()
+    }
+
+    pub fn is_allowed_borrower(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        offer_id: u32,
+        account_id: AccountId,
+    ) -> bool {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let offer = self
+            .internal_get_offer(&contract_and_token_id, offer_id)
+            .expect("No offer");
+        check_allowed_borrower(&offer, &account_id).is_ok()
+    }
+}
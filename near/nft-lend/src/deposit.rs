@@ -0,0 +1,178 @@
+use crate::*;
+use near_sdk::promise_result_as_success;
+
+/// borrower deposits: balances per account and currency a borrower keeps with the market
+/// to pay its loans from, the interest of a rollover or the whole payoff with auto_repay.
+/// Funded with deposit_by_near or ft_on_transfer action "deposit"
+
+/// seconds before the deadline of a loan a keeper can auto_repay it
+const AUTO_REPAY_WINDOW: u64 = 86400;
+
+/// ft_on_transfer msg of a deposit, which is not tied to a sale
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositArgs {
+    pub action: String,
+}
+
+pub(crate) fn deposit_key(account_id: &str, ft_token_id: &str) -> String {
+    format!("{}{}{}", account_id, DELIMETER, ft_token_id)
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn deposit_by_near(&mut self) {
+        let amount = env::attached_deposit();
+        assert!(amount > 0, "Amount must be greater than 0");
+        self.internal_deposit(&env::predecessor_account_id(), &"near".to_string(), amount);
+    }
+
+    #[payable]
+    pub fn withdraw_deposit(&mut self, ft_token_id: AccountId, amount: U128) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.internal_withdraw_deposit(&account_id, &ft_token_id, amount.0);
+        self.internal_send(
+            &ft_token_id,
+            account_id.clone(),
+            amount.0,
+            "deposit withdrawal",
+        )
+        .then(ext_self::resolve_withdraw_deposit(
+            account_id,
+            ft_token_id,
+            amount,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_RESOLVE_TRANSFER,
+        ))
+    }
+
+    /// returns the amount withdrawn, a failed transfer goes back on the deposit
+    #[private]
+    pub fn resolve_withdraw_deposit(
+        &mut self,
+        account_id: AccountId,
+        ft_token_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        if promise_result_as_success().is_some() {
+            return amount;
+        }
+        let key = deposit_key(&account_id, &ft_token_id);
+        let balance = self.deposits.get(&key).unwrap_or(0);
+        self.deposits.insert(&key, &(balance + amount.0));
+        U128(0)
+    }
+
+    /// borrower consent to have keepers pay its loan back from its deposit, see auto_repay
+    pub fn set_auto_repay(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        auto_repay: bool,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert_borrower(&sale);
+        sale.auto_repay = auto_repay;
+        sale.updated_at = U64(env::block_timestamp() / 1000000000);
+        self.sales.insert(&contract_and_token_id, &sale);
+    }
+
+    /// pays back a running loan from the borrower's deposit. The borrower can call it at any
+    /// time, a keeper only once the borrower opted in with set_auto_repay, within
+    /// AUTO_REPAY_WINDOW of the deadline and not for a loan set to roll over. The payoff is
+    /// the one at execution time, what is left stays in the deposit for withdraw_deposit
+    pub fn auto_repay(&mut self, nft_contract_id: AccountId, token_id: String) -> U128 {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert!(
+            sale.status == LoanStatus::Processing as u32,
+            "Loan is not in processing"
+        );
+        // the receipt holder, not the borrower, owns the way out of the loan
+        assert!(
+            self.internal_receipt_of(&sale).is_none(),
+            "Loans with an obligation receipt cannot be auto repaid"
+        );
+        if user_account_id() != sale.owner_id {
+            assert!(sale.auto_repay, "Borrower did not opt in to auto repay");
+            assert!(!sale.auto_renew, "Loan is set to roll over");
+            let deadline = loan_deadline(&sale).expect("Loan has no deadline yet");
+            assert!(
+                env::block_timestamp() / 1000000000 + AUTO_REPAY_WINDOW >= deadline,
+                "Auto repay opens at {}",
+                deadline - AUTO_REPAY_WINDOW
+            );
+        }
+        let offers = self.internal_offers(&contract_and_token_id);
+        let pay_amount = sale_payoff(
+            &sale,
+            &offers,
+            env::block_timestamp() / 1000000000,
+            self.internal_fee_bps(&sale.owner_id),
+            self.internal_rate_index(&sale, env::block_timestamp() / 1000000000),
+        )
+        .total;
+        self.internal_withdraw_deposit(&sale.owner_id, &sale.loan_currency, pay_amount);
+        self.process_payback_loan(
+            nft_contract_id,
+            token_id,
+            sale.loan_currency.clone(),
+            U128(pay_amount),
+            sale.owner_id.clone(),
+        );
+        U128(pay_amount)
+    }
+
+    /// views
+
+    pub fn get_deposit(&self, account_id: AccountId, ft_token_id: AccountId) -> U128 {
+        U128(
+            self.deposits
+                .get(&deposit_key(&account_id, &ft_token_id))
+                .unwrap_or(0),
+        )
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_deposit(
+        &mut self,
+        account_id: &AccountId,
+        ft_token_id: &AccountId,
+        amount: Balance,
+    ) {
+        assert!(
+            self.ft_token_ids.contains(ft_token_id),
+            "Token {} is not supported",
+            ft_token_id
+        );
+        let key = deposit_key(account_id, ft_token_id);
+        let balance = self.deposits.get(&key).unwrap_or(0);
+        self.deposits.insert(&key, &(balance + amount));
+    }
+
+    pub(crate) fn internal_withdraw_deposit(
+        &mut self,
+        account_id: &AccountId,
+        ft_token_id: &AccountId,
+        amount: Balance,
+    ) {
+        let key = deposit_key(account_id, ft_token_id);
+        let balance = self.deposits.get(&key).unwrap_or(0);
+        assert!(
+            amount <= balance,
+            "Insufficient deposit: {}, requested {}",
+            balance,
+            amount
+        );
+        if balance == amount {
+            self.deposits.remove(&key);
+        } else {
+            self.deposits.insert(&key, &(balance - amount));
+        }
+    }
+}
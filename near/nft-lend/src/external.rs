@@ -13,4 +13,17 @@ trait ExtContract {
     );
 
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+
+    fn nft_token(&self, token_id: TokenId);
+
+    fn nft_is_approved(
+        &self,
+        token_id: TokenId,
+        approved_account_id: AccountId,
+        approval_id: Option<u64>,
+    );
+
+    fn nft_mint(&mut self, token_id: TokenId, metadata: NoteMetadata, receiver_id: AccountId);
+
+    fn nft_burn(&mut self, token_id: TokenId);
 }
@@ -21,6 +21,32 @@ impl FungibleTokenReceiver for Contract {
     ) -> PromiseOrValue<U128> {
         // PromiseOrValue::Value(U128(0))
 
+        // deposits, stakes and reward funding are not tied to a sale, see deposit.rs,
+        // staking.rs and rewards.rs
+        if let Ok(DepositArgs { action }) = near_sdk::serde_json::from_str(&msg) {
+            if action == "deposit" {
+                self.internal_deposit(&sender_id, &env::predecessor_account_id(), amount.0);
+                return PromiseOrValue::Value(U128(0));
+            } else if action == "stake" {
+                self.internal_stake(&sender_id, &env::predecessor_account_id(), amount.0);
+                return PromiseOrValue::Value(U128(0));
+            } else if action == "fund_rewards" {
+                self.internal_fund_rewards(&env::predecessor_account_id(), amount.0);
+                return PromiseOrValue::Value(U128(0));
+            }
+        }
+
+        // several offers from one transfer, see batch.rs
+        if let Ok(BatchOfferArgs { offers }) = near_sdk::serde_json::from_str(&msg) {
+            let refund = self.internal_batch_offer(
+                &sender_id,
+                &env::predecessor_account_id(),
+                amount.0,
+                offers,
+            );
+            return PromiseOrValue::Value(U128(refund));
+        }
+
         let PurchaseArgs {
             nft_contract_id,
             token_id,
@@ -29,6 +55,13 @@ impl FungibleTokenReceiver for Contract {
             loan_duration,
             loan_interest_rate,
             available_at,
+            negotiation_id,
+            expires_in,
+            renewable,
+            min_on_time_rate,
+            referrer_id,
+            variable_rate,
+            allowed_borrower,
         } = near_sdk::serde_json::from_str(&msg).expect("Invalid PurchaseArgs");
 
         let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
@@ -43,6 +76,13 @@ impl FungibleTokenReceiver for Contract {
         assert!(amount.0 > 0, "Amount must be greater than 0");
         if action == "offer_now" {
             assert_ne!(sale.owner_id, sender_id, "Cannot buy your own sale.");
+            assert_sale_available(&sale);
+            assert_allowed_lender(&sale, &sender_id);
+            assert_referrers(&sale, &sender_id, referrer_id.as_ref());
+            assert!(
+                sale.loan_config != LoanConfig::Tranched as u32,
+                "Tranched sale must be filled by offers"
+            );
             let log_message = format!(
                 "Principle amount {}, real amount {}",
                 sale.loan_principal_amount, amount.0
@@ -57,7 +97,7 @@ impl FungibleTokenReceiver for Contract {
             sale.updated_at = U64(env::block_timestamp() / 1000000000);
             sale.started_at = U64(env::block_timestamp() / 1000000000);
             let new_offer = Offer {
-                offer_id: sale.offers.len() as u32 + 1,
+                offer_id: 0,
                 lender_id: sale.lender.clone(),
                 loan_principal_amount: sale.loan_principal_amount,
                 loan_duration: sale.loan_duration,
@@ -67,8 +107,15 @@ impl FungibleTokenReceiver for Contract {
                 started_at: U64(env::block_timestamp() / 1000000000),
                 status: LoanStatus::Processing as u32,
                 available_at: available_at,
+                renewable: renewable.unwrap_or(false),
+                min_on_time_rate: min_on_time_rate.unwrap_or(0),
+                referrer_id: referrer_id,
+                variable_rate: false,
+                rate_index: U128(0),
+                allowed_borrower: allowed_borrower,
             };
-            sale.offers.push(new_offer);
+            assert_allowed_borrower(&new_offer, &sale.owner_id);
+            self.internal_add_offer(&contract_and_token_id, new_offer);
             self.sales.insert(&contract_and_token_id, &sale);
             self.process_purchase(
                 nft_contract_id.into(),
@@ -77,17 +124,30 @@ impl FungibleTokenReceiver for Contract {
                 amount,
                 sale.approval_id,
                 sale.owner_id,
-                sale.lender,
             )
             .into()
             //
         } else if action == "offer" {
             assert_ne!(sale.owner_id, sender_id, "Cannot buy your own sale.");
-            sale.lender = sender_id;
+            assert!(
+                sale.status == LoanStatus::Open as u32,
+                "invalid loan status",
+            );
+            // the transferred amount is what the offer escrows
+            assert!(
+                amount.0 == loan_principal_amount.0,
+                "Deposit amount must be equal input loan_principal_amount"
+            );
+            assert_allowed_lender(&sale, &sender_id);
+            assert_referrers(&sale, &sender_id, referrer_id.as_ref());
+            self.assert_offer_terms(&sale, loan_principal_amount.0, loan_duration);
+            if variable_rate == Some(true) {
+                self.assert_variable_offer(&sale);
+            }
             //insert offer
             let new_offer = Offer {
-                offer_id: sale.offers.len() as u32 + 1,
-                lender_id: sale.lender.clone(),
+                offer_id: 0,
+                lender_id: sender_id,
                 loan_principal_amount: loan_principal_amount.0,
                 loan_duration: loan_duration,
                 loan_interest_rate: loan_interest_rate,
@@ -96,10 +156,15 @@ impl FungibleTokenReceiver for Contract {
                 started_at: U64(0),
                 status: LoanStatus::Open as u32,
                 available_at: available_at,
+                renewable: renewable.unwrap_or(false),
+                min_on_time_rate: min_on_time_rate.unwrap_or(0),
+                referrer_id: referrer_id,
+                variable_rate: variable_rate.unwrap_or(false),
+                rate_index: U128(0),
+                allowed_borrower: allowed_borrower,
             };
-            sale.offers.push(new_offer);
-            //
-            self.sales.insert(&contract_and_token_id, &sale);
+            assert_allowed_borrower(&new_offer, &sale.owner_id);
+            self.internal_add_offer(&contract_and_token_id, new_offer);
             PromiseOrValue::Value(U128(0))
             //
         } else if action == "pay_back_loan" {
@@ -107,18 +172,38 @@ impl FungibleTokenReceiver for Contract {
                 amount.0 > sale.loan_principal_amount,
                 "Amount must greater than loan principal amount ",
             );
-            sale.status = LoanStatus::Done as u32;
-            sale.updated_at = U64(env::block_timestamp() / 1000000000);
-            self.sales.insert(&contract_and_token_id, &sale);
-            self.process_payback_loan(
+            PromiseOrValue::Value(self.process_payback_loan(
                 nft_contract_id.into(),
                 token_id,
                 ft_token_id,
                 amount,
                 sale.owner_id,
-                sale.lender,
-            )
-            .into()
+            ))
+        } else if action == "counter_negotiation" {
+            PromiseOrValue::Value(U128(self.internal_counter_negotiation(
+                negotiation_id.expect("negotiation_id is required").0,
+                sender_id,
+                &ft_token_id,
+                amount.0,
+                loan_principal_amount.0,
+                loan_duration,
+                loan_interest_rate,
+                expires_in.expect("expires_in is required").0,
+            )))
+        } else if action == "accept_negotiation" {
+            PromiseOrValue::Value(U128(self.internal_accept_negotiation(
+                negotiation_id.expect("negotiation_id is required").0,
+                sender_id,
+                &ft_token_id,
+                amount.0,
+            )))
+        } else if action == "buyout_claim" {
+            PromiseOrValue::Value(U128(self.internal_buyout_claim(
+                contract_and_token_id,
+                sender_id,
+                ft_token_id,
+                amount.0,
+            )))
         } else {
             PromiseOrValue::Value(U128(0))
         }
@@ -1,4 +1,14 @@
 use crate::*;
+use near_sdk::{promise_result_as_success, PromiseResult};
+use pawn_interest::{InterestConfig, LoanTerms, Payoff};
+
+/// the part of a NEP-171 nft_token result we read
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct JsonToken {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+}
 
 pub(crate) fn hash_account_id(account_id: &AccountId) -> CryptoHash {
     let mut hash = CryptoHash::default();
@@ -6,6 +16,210 @@ pub(crate) fn hash_account_id(account_id: &AccountId) -> CryptoHash {
     hash
 }
 
+/// owner from the result of a preceding nft_token call, None if the token does not exist
+pub(crate) fn token_owner() -> Option<AccountId> {
+    promise_result_as_success()
+        .and_then(|value| near_sdk::serde_json::from_slice::<Option<JsonToken>>(&value).ok())
+        .flatten()
+        .map(|token| token.owner_id)
+}
+
+/// owner from each result of a preceding joint nft_token call, in call order
+pub(crate) fn token_owners() -> Vec<Option<AccountId>> {
+    (0..env::promise_results_count())
+        .map(|i| match env::promise_result(i) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<Option<JsonToken>>(&value)
+                    .ok()
+                    .flatten()
+                    .map(|token| token.owner_id)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// whether each preceding joint nft_token call failed, in call order. A failed lookup says
+/// nothing about where the NFT is
+pub(crate) fn failed_lookups() -> Vec<bool> {
+    (0..env::promise_results_count())
+        .map(|i| !matches!(env::promise_result(i), PromiseResult::Successful(_)))
+        .collect()
+}
+
+/// repayment breakdown for a loan repaid at `pay_at` (seconds), `fee_bps` is the
+/// borrower's fee, see staking.rs
+pub(crate) fn loan_payoff(
+    loan_principal_amount: u128,
+    loan_duration: u32,
+    interest_rate: u32,
+    loan_started_at: u64,
+    pay_at: u64,
+    fee_bps: u32,
+) -> Payoff {
+    let terms = LoanTerms {
+        principal: loan_principal_amount,
+        duration: loan_duration as u64,
+        interest_rate,
+        started_at: loan_started_at,
+    };
+    InterestConfig {
+        fee_bps,
+        ..InterestConfig::default()
+    }
+    .payoff(&terms, pay_at)
+    .expect("pay amount overflow")
+}
+
+/// one lender position of a loan, the offer it was funded by
+pub(crate) struct LenderPayoff {
+    pub offer_id: u32,
+    pub lender_id: AccountId,
+    pub referrer_id: Option<AccountId>,
+    pub payoff: Payoff,
+}
+
+/// what each lender of `sale` is owed at `pay_at`, one entry per tranche for tranched sales
+/// `rate_index` is the index of the sale's rate pool at `pay_at`, see variable_rate.rs
+pub(crate) fn sale_payoffs(
+    sale: &Sale,
+    offers: &[Offer],
+    pay_at: u64,
+    fee_bps: u32,
+    rate_index: u128,
+) -> Vec<LenderPayoff> {
+    let mut payoffs = vec![];
+    for offer in offers.iter() {
+        if offer.status == LoanStatus::Processing as u32 {
+            // a single loan carries the accepted terms on the sale itself
+            let (principal, interest_rate) = if sale.loan_config == LoanConfig::Tranched as u32 {
+                (offer.loan_principal_amount, offer.loan_interest_rate)
+            } else {
+                (sale.loan_principal_amount, sale.loan_interest_rate)
+            };
+            let payoff = if offer.variable_rate {
+                variable_payoff(principal, offer.rate_index.0, rate_index, fee_bps)
+            } else if sale.loan_config == LoanConfig::Callable as u32 {
+                callable_payoff(principal, interest_rate, sale.started_at.0, pay_at, fee_bps)
+            } else {
+                loan_payoff(
+                    principal,
+                    sale.loan_duration,
+                    interest_rate,
+                    sale.started_at.0,
+                    pay_at,
+                    fee_bps,
+                )
+            };
+            payoffs.push(LenderPayoff {
+                offer_id: offer.offer_id,
+                lender_id: offer.lender_id.clone(),
+                referrer_id: offer.referrer_id.clone(),
+                payoff,
+            });
+        }
+    }
+    payoffs
+}
+
+/// sum of sale_payoffs
+pub(crate) fn sale_payoff(
+    sale: &Sale,
+    offers: &[Offer],
+    pay_at: u64,
+    fee_bps: u32,
+    rate_index: u128,
+) -> Payoff {
+    let mut total = Payoff::default();
+    for LenderPayoff { payoff, .. } in sale_payoffs(sale, offers, pay_at, fee_bps, rate_index) {
+        total.principal += payoff.principal;
+        total.interest += payoff.interest;
+        total.discounted_interest += payoff.discounted_interest;
+        total.fee += payoff.fee;
+        total.total += payoff.total;
+    }
+    total
+}
+
+/// bounty of a keeper liquidating `sale` with `bounty_rate` basis points of its principal,
+/// capped by the protocol `fees` of its currency. The borrower and the lenders, `lender_ids`,
+/// get none: they could default or liquidate on purpose to drain the fees
+pub(crate) fn liquidation_bounty(
+    sale: &Sale,
+    lender_ids: &[AccountId],
+    keeper_id: &AccountId,
+    bounty_rate: u32,
+    fees: Balance,
+) -> Balance {
+    if keeper_id == &sale.owner_id || keeper_id == &sale.lender || lender_ids.contains(keeper_id) {
+        return 0;
+    }
+    min(
+        sale.loan_principal_amount * bounty_rate as u128 / 10000,
+        fees,
+    )
+}
+
+/// key of an offer in Contract.offers
+pub(crate) fn offer_key(contract_and_token_id: &str, offer_id: u32) -> String {
+    format!("{}{}{}", contract_and_token_id, DELIMETER, offer_id)
+}
+
+/// principal plus interest for the whole duration, what a liquidated tranche is bought out for
+pub(crate) fn tranche_claim(sale: &Sale, offer: &Offer) -> u128 {
+    let started_at = sale.started_at.0;
+    let payoff = loan_payoff(
+        offer.loan_principal_amount,
+        sale.loan_duration,
+        offer.loan_interest_rate,
+        started_at,
+        started_at + sale.loan_duration as u64,
+        default_fee_bps(),
+    );
+    payoff.total - payoff.fee
+}
+
+/// the user behind the call. A NEP-366 delegate action runs with the user as predecessor and
+/// the relayer as signer, so users are never identified by signer_account_id
+pub(crate) fn user_account_id() -> AccountId {
+    env::predecessor_account_id()
+}
+
+pub(crate) fn assert_borrower(sale: &Sale) {
+    let user_id = user_account_id();
+    assert!(
+        sale.owner_id == user_id,
+        "invalid owner owner's loan:{}, caller:{}",
+        sale.owner_id,
+        user_id,
+    );
+}
+
+/// a sale can be funded once it is open and its available_at has passed
+pub(crate) fn assert_sale_available(sale: &Sale) {
+    assert!(
+        sale.status == LoanStatus::Open as u32,
+        "Loan is in proccessing "
+    );
+    let now = env::block_timestamp() / 1000000000;
+    assert!(
+        sale.available_at <= now,
+        "Sale is available at {}, now is {}",
+        sale.available_at,
+        now
+    );
+}
+
+pub(crate) fn assert_lender(offer: &Offer) {
+    let user_id = user_account_id();
+    assert!(
+        offer.lender_id == user_id,
+        "invalid lender offer's lender:{}, caller:{}",
+        offer.lender_id,
+        user_id,
+    );
+}
+
 impl Contract {
     pub(crate) fn assert_owner(&self) {
         assert_eq!(
@@ -15,6 +229,258 @@ impl Contract {
         );
     }
 
+    /// pays out `amount` of `ft_token_id` ("near" for native NEAR)
+    pub(crate) fn internal_send(
+        &self,
+        ft_token_id: &AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        memo: &str,
+    ) -> Promise {
+        if ft_token_id == "near" {
+            Promise::new(receiver_id).transfer(amount)
+        } else {
+            ext_contract::ft_transfer(
+                receiver_id,
+                U128(amount),
+                Some(memo.to_string()),
+                ft_token_id,
+                1,
+                GAS_FOR_FT_TRANSFER,
+            )
+        }
+    }
+
+    pub(crate) fn internal_add_protocol_fee(&mut self, ft_token_id: &AccountId, fee: Balance) {
+        let balance = self.protocol_fees.get(ft_token_id).unwrap_or(0);
+        self.protocol_fees.insert(ft_token_id, &(balance + fee));
+    }
+
+    /// tranches must share the sale's duration and cannot exceed its principal
+    pub(crate) fn assert_offer_terms(
+        &self,
+        sale: &Sale,
+        loan_principal_amount: u128,
+        loan_duration: u32,
+    ) {
+        if let Err(message) = self.check_offer_terms(sale, loan_principal_amount, loan_duration) {
+            env::panic(message.as_bytes());
+        }
+    }
+
+    pub(crate) fn check_offer_terms(
+        &self,
+        sale: &Sale,
+        loan_principal_amount: u128,
+        loan_duration: u32,
+    ) -> Result<(), String> {
+        if sale.loan_config == LoanConfig::Tranched as u32 {
+            if loan_duration != sale.loan_duration {
+                return Err("Tranche duration must equal loan duration".to_string());
+            }
+            if loan_principal_amount > sale.loan_principal_amount {
+                return Err("Tranche is larger than loan principal amount".to_string());
+            }
+        }
+        if sale.loan_config == LoanConfig::Callable as u32 && loan_duration < MIN_CALL_NOTICE {
+            return Err(format!(
+                "Call notice must be at least {} seconds",
+                MIN_CALL_NOTICE
+            ));
+        }
+        Ok(())
+    }
+
+    /// all offers on the current listing of a sale, by offer_id
+    pub(crate) fn internal_offers(&self, contract_and_token_id: &ContractAndTokenId) -> Vec<Offer> {
+        let count = self.offer_counts.get(contract_and_token_id).unwrap_or(0);
+        (1..=count)
+            .filter_map(|offer_id| self.offers.get(&offer_key(contract_and_token_id, offer_id)))
+            .collect()
+    }
+
+    pub(crate) fn internal_get_offer(
+        &self,
+        contract_and_token_id: &ContractAndTokenId,
+        offer_id: u32,
+    ) -> Option<Offer> {
+        self.offers.get(&offer_key(contract_and_token_id, offer_id))
+    }
+
+    pub(crate) fn internal_update_offer(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        offer: &Offer,
+    ) {
+        let key = offer_key(contract_and_token_id, offer.offer_id);
+        if !offer.variable_rate {
+            self.offers.insert(&key, offer);
+            return;
+        }
+        // a variable offer moves through the pool of its collection and currency, see
+        // variable_rate.rs
+        let sale = self.sales.get(contract_and_token_id).expect("No sale");
+        let old = self.offers.get(&key);
+        let mut offer = offer.clone();
+        offer.rate_index = self.internal_track_variable_offer(&sale, old.as_ref(), &offer);
+        self.offers.insert(&key, &offer);
+    }
+
+    /// offers below the currency's minimum principal are rejected as spam
+    pub(crate) fn assert_min_offer_principal(
+        &self,
+        ft_token_id: &AccountId,
+        loan_principal_amount: u128,
+    ) {
+        if let Err(message) = self.check_min_offer_principal(ft_token_id, loan_principal_amount) {
+            env::panic(message.as_bytes());
+        }
+    }
+
+    pub(crate) fn check_min_offer_principal(
+        &self,
+        ft_token_id: &AccountId,
+        loan_principal_amount: u128,
+    ) -> Result<(), String> {
+        let min_principal = self.min_offer_principals.get(ft_token_id).unwrap_or(0);
+        if loan_principal_amount < min_principal {
+            return Err(format!(
+                "Offer principal {} is below the minimum of {}",
+                loan_principal_amount, min_principal
+            ));
+        }
+        Ok(())
+    }
+
+    /// what internal_add_offer requires of a new offer
+    pub(crate) fn check_new_offer(
+        &self,
+        contract_and_token_id: &ContractAndTokenId,
+        offer: &Offer,
+    ) -> Result<(), String> {
+        let sale = self
+            .sales
+            .get(contract_and_token_id)
+            .ok_or_else(|| "No sale".to_string())?;
+        self.check_min_offer_principal(&sale.loan_currency, offer.loan_principal_amount)?;
+        let offer_id = self.offer_counts.get(contract_and_token_id).unwrap_or(0) + 1;
+        // a full listing is reset by cancelling and listing the NFT again
+        if offer_id > self.max_offers_per_sale {
+            return Err(format!(
+                "Sale already has {} offers",
+                self.max_offers_per_sale
+            ));
+        }
+        // the lender's own listings keep their storage, see storage_withdraw
+        let balance = self.storage_deposits.get(&offer.lender_id).unwrap_or(0);
+        let listings = self
+            .by_owner_id
+            .get(&offer.lender_id)
+            .map(|s| s.len())
+            .unwrap_or_default();
+        let required = STORAGE_PER_OFFER + u128::from(listings) * STORAGE_PER_SALE;
+        if balance < required {
+            return Err(format!(
+                "Insufficient storage paid: {}, an offer requires {}",
+                balance, required
+            ));
+        }
+        Ok(())
+    }
+
+    /// stores `offer` under the next offer_id of the sale and returns that id,
+    /// STORAGE_PER_OFFER is taken from the lender's storage deposit
+    pub(crate) fn internal_add_offer(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        mut offer: Offer,
+    ) -> u32 {
+        if let Err(message) = self.check_new_offer(contract_and_token_id, &offer) {
+            env::panic(message.as_bytes());
+        }
+        let offer_id = self.offer_counts.get(contract_and_token_id).unwrap_or(0) + 1;
+        let balance = self.storage_deposits.get(&offer.lender_id).unwrap_or(0);
+        self.storage_deposits
+            .insert(&offer.lender_id, &(balance - STORAGE_PER_OFFER));
+
+        self.offer_counts.insert(contract_and_token_id, &offer_id);
+        offer.offer_id = offer_id;
+        self.internal_update_offer(contract_and_token_id, &offer);
+        offer_id
+    }
+
+    /// gives STORAGE_PER_OFFER back to the lender once its offer is cancelled or settled
+    pub(crate) fn internal_release_offer_storage(&mut self, lender_id: &AccountId) {
+        let balance = self.storage_deposits.get(lender_id).unwrap_or(0);
+        self.storage_deposits
+            .insert(lender_id, &(balance + STORAGE_PER_OFFER));
+    }
+
+    /// drops the offers of a finished listing before the NFT is listed again, the ones still
+    /// open are refunded first
+    pub(crate) fn internal_clear_offers(&mut self, contract_and_token_id: &ContractAndTokenId) {
+        if let Some(sale) = self.sales.get(contract_and_token_id) {
+            for offer in self.internal_offers(contract_and_token_id) {
+                if offer.status == LoanStatus::Open as u32 {
+                    self.internal_cancel_offer(contract_and_token_id, &sale, offer);
+                }
+            }
+        }
+        if let Some(count) = self.offer_counts.remove(contract_and_token_id) {
+            for offer_id in 1..=count {
+                self.offers
+                    .remove(&offer_key(contract_and_token_id, offer_id));
+            }
+        }
+    }
+
+    /// cancels an open sale, refunds its open offers and returns an escrowed NFT
+    pub(crate) fn internal_cancel_sale(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        mut sale: Sale,
+    ) {
+        let now = U64(env::block_timestamp() / 1000000000);
+        for mut offer in self.internal_offers(contract_and_token_id) {
+            if offer.status == LoanStatus::Open as u32 {
+                self.internal_send(
+                    &sale.loan_currency,
+                    offer.lender_id.clone(),
+                    offer.loan_principal_amount,
+                    "refund from market",
+                );
+                offer.status = LoanStatus::Canceled as u32;
+                offer.updated_at = now;
+                self.internal_update_offer(contract_and_token_id, &offer);
+                self.internal_release_offer_storage(&offer.lender_id);
+            }
+        }
+        sale.status = LoanStatus::Canceled as u32;
+        sale.updated_at = now;
+        self.sales.insert(contract_and_token_id, &sale);
+        self.internal_update_stats(&sale, |stats| {
+            stats.open = stats.open.saturating_sub(1);
+            stats.canceled += 1;
+        });
+        if sale.escrowed {
+            ext_contract::nft_transfer(
+                sale.owner_id,
+                sale.token_id,
+                0,
+                "cancel loan".to_string(),
+                &sale.nft_contract_id,
+                1,
+                GAS_FOR_NFT_TRANSFER,
+            );
+        }
+    }
+
+    /// loan_duration and the grace period have both passed, or the notice of a called loan
+    pub(crate) fn internal_is_overdue(&self, sale: &Sale) -> bool {
+        let now = env::block_timestamp() / 1000000000;
+        loan_deadline(sale).map_or(false, |expired| expired < now)
+    }
+
     // pub(crate) fn internal_remove_sale(
     //     &mut self,
     //     nft_contract_id: AccountId,
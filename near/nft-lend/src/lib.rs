@@ -1,6 +1,18 @@
+use crate::batch::*;
+use crate::bundle::*;
+use crate::callable::*;
+use crate::counterparty::*;
+use crate::deposit::*;
 use crate::external::*;
 use crate::internal::*;
+use crate::negotiation::*;
+use crate::note::*;
+use crate::referral::*;
+use crate::reputation::*;
 use crate::sale::*;
+use crate::staking::*;
+use crate::stats::*;
+use crate::variable_rate::*;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
 use near_sdk::env::STORAGE_PRICE_PER_BYTE;
@@ -13,12 +25,28 @@ use near_sdk::{
 use std::cmp::min;
 use std::collections::HashMap;
 
+mod batch;
+mod bundle;
+mod callable;
+mod counterparty;
+mod deposit;
 mod external;
 mod ft_callbacks;
 mod internal;
+mod migrate;
+mod negotiation;
 mod nft_callbacks;
+mod note;
+mod referral;
+mod reputation;
+mod rewards;
+mod rollover;
 mod sale;
 mod sale_views;
+mod staking;
+mod stats;
+mod tranche;
+mod variable_rate;
 
 near_sdk::setup_alloc!();
 
@@ -28,13 +56,43 @@ const GAS_FOR_FT_TRANSFER: Gas = 5_000_000_000_000;
 /// greedy max Tgas for resolve_purchase
 const GAS_FOR_ROYALTIES: Gas = 115_000_000_000_000;
 const GAS_FOR_NFT_TRANSFER: Gas = 15_000_000_000_000;
+const GAS_FOR_NFT_TOKEN: Gas = 10_000_000_000_000;
+/// resolve_verify_listing, the refunds of a cancelled listing come on top
+const GAS_FOR_RESOLVE_VERIFY: Gas = 20_000_000_000_000;
+/// refund of one open offer of a cancelled listing
+const GAS_PER_OFFER_REFUND: Gas = GAS_FOR_FT_TRANSFER + 5_000_000_000_000;
 const BID_HISTORY_LENGTH_DEFAULT: u8 = 1;
 const NO_DEPOSIT: Balance = 0;
 const STORAGE_PER_SALE: u128 = 1000 * STORAGE_PRICE_PER_BYTE;
+/// taken from the lender's storage deposit for each offer it makes
+const STORAGE_PER_OFFER: u128 = 500 * STORAGE_PRICE_PER_BYTE;
+/// taken from the borrower's storage deposit for each negotiation it opens
+const STORAGE_PER_NEGOTIATION: u128 = 500 * STORAGE_PRICE_PER_BYTE;
+/// taken from the storage deposit of the account adding a step to a negotiation's history
+const STORAGE_PER_NEGOTIATION_STEP: u128 = 150 * STORAGE_PRICE_PER_BYTE;
+const DEFAULT_MAX_OFFERS_PER_SALE: u32 = 20;
+/// NFTs a sale can bundle besides its own, bounded by the gas of moving them all at once
+const MAX_BUNDLE_ITEMS: usize = 4;
+/// NFT contracts an account can allow relayed listings from, covered by its STORAGE_PER_SALE
+const MAX_RELAYED_NFT_CONTRACTS: usize = 10;
+/// seconds a borrower may still pay back after loan_duration has passed
+const LOAN_GRACE_PERIOD: u128 = 2 * 86400;
+const GAS_FOR_NOTE: Gas = 10_000_000_000_000;
+/// nft_transfer or ft_transfer to the note holder + nft_burn
+const GAS_FOR_RESOLVE_NOTE: Gas = 40_000_000_000_000;
+const GAS_FOR_RESOLVE_CALL: Gas = 10_000_000_000_000;
+/// puts a balance back when its payout failed
+const GAS_FOR_RESOLVE_TRANSFER: Gas = 10_000_000_000_000;
+/// gas kept aside for each loan in liquidate_many (note lookup, nft_transfer, receipt burn
+/// + keeper bounty and its resolve)
+const GAS_PER_LIQUIDATION: Gas = 2 * GAS_FOR_NOTE
+    + GAS_FOR_RESOLVE_NOTE
+    + GAS_FOR_NFT_TRANSFER
+    + GAS_FOR_FT_TRANSFER
+    + GAS_FOR_RESOLVE_TRANSFER;
 static DELIMETER: &str = "||";
 
 pub type SaleConditions = HashMap<FungibleTokenId, U128>;
-pub type Offers = Vec<Offer>;
 pub type TokenId = String;
 pub type TokenType = Option<String>;
 pub type FungibleTokenId = AccountId;
@@ -53,14 +111,67 @@ pub struct StorageBalanceBounds {
 pub struct Contract {
     pub owner_id: AccountId,
     pub sales: UnorderedMap<ContractAndTokenId, Sale>,
+    /// offers of the current listing of each sale, keyed by sale and offer_id
+    pub offers: UnorderedMap<String, Offer>,
+    /// last offer_id handed out per sale
+    pub offer_counts: LookupMap<ContractAndTokenId, u32>,
+    /// offers a single listing accepts, cancelled ones included
+    pub max_offers_per_sale: u32,
+    /// smallest offer principal per loan currency, missing means no minimum
+    pub min_offer_principals: LookupMap<FungibleTokenId, Balance>,
+    /// further NFTs pledged with a sale, see bundle.rs
+    pub bundle_items: LookupMap<ContractAndTokenId, Vec<BundleItem>>,
+    /// sale each bundle item was added to
+    pub bundled_in: LookupMap<ContractAndTokenId, ContractAndTokenId>,
+    /// borrower deposits by account and currency, see deposit.rs
+    pub deposits: LookupMap<String, Balance>,
+    /// see stats.rs
+    pub currency_stats: LookupMap<FungibleTokenId, LoanStats>,
+    /// by nft_contract_id and loan currency
+    pub collection_stats: UnorderedMap<String, LoanStats>,
+    /// credit history by account, see reputation.rs
+    pub reputations: LookupMap<AccountId, Reputation>,
+    /// basis points of the protocol fee credited to each referrer of a loan
+    pub referral_fee_rate: u32,
+    /// claimable referral rewards by referrer and currency
+    pub referral_rewards: LookupMap<String, Balance>,
+    /// NFTPAWN contract paying liquidity-mining rewards, None disables them, see rewards.rs
+    pub reward_token_id: Option<AccountId>,
+    /// by loan currency, or by nft_contract_id and loan currency
+    pub reward_rates: LookupMap<String, u128>,
+    /// funded rewards not yet accrued
+    pub reward_pool: Balance,
+    /// claimable rewards by account
+    pub rewards: LookupMap<AccountId, Balance>,
+    /// NFTPAWN staked by account, see staking.rs
+    pub stakes: LookupMap<AccountId, Stake>,
+    /// fee discounts by stake, the lowest fee_bps reached applies
+    pub fee_tiers: Vec<FeeTier>,
+    /// utilization curve per currency, see variable_rate.rs
+    pub rate_models: LookupMap<FungibleTokenId, RateModel>,
+    /// variable-rate offers and rate index per collection and currency, see rate_pool_id
+    pub rate_pools: LookupMap<String, RatePool>,
     pub by_owner_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
     pub by_nft_contract_id: LookupMap<AccountId, UnorderedSet<TokenId>>,
     pub by_nft_token_type: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
     pub ft_token_ids: UnorderedSet<AccountId>,
     pub storage_deposits: LookupMap<AccountId, Balance>,
+    /// fees of repaid loans per currency. Lenders are paid principal and interest only, the
+    /// fee pays keeper bounties and referral shares, the owner withdraws the rest
+    pub protocol_fees: LookupMap<FungibleTokenId, Balance>,
+    pub liquidation_bounty_rate: u32,
+    /// nft-note contract minting promissory notes for lender positions, None disables notes
+    pub note_contract_id: Option<AccountId>,
+    pub negotiations: UnorderedMap<u64, Negotiation>,
+    pub negotiations_by_sale: LookupMap<ContractAndTokenId, UnorderedSet<u64>>,
+    pub next_negotiation_id: u64,
+    /// NFT contracts each account lets list its tokens in relayed transactions, see
+    /// nft_callbacks.rs
+    pub relayed_nft_contracts: LookupMap<AccountId, Vec<AccountId>>,
 }
 
-/// Helper structure to for keys of the persistent collections.
+/// Helper structure to for keys of the persistent collections. Variants are only appended,
+/// the collections of a deployed contract keep the prefix of their variant's index
 #[derive(BorshStorageKey, BorshSerialize)]
 pub enum StorageKey {
     Sales,
@@ -72,6 +183,26 @@ pub enum StorageKey {
     ByNFTTokenTypeInner { token_type_hash: CryptoHash },
     FTTokenIds,
     StorageDeposits,
+    Offers,
+    OfferCounts,
+    MinOfferPrincipals,
+    BundleItems,
+    BundledIn,
+    Deposits,
+    CurrencyStats,
+    CollectionStats,
+    Reputations,
+    ReferralRewards,
+    RewardRates,
+    Rewards,
+    Stakes,
+    RateModels,
+    RatePools,
+    ProtocolFees,
+    Negotiations,
+    NegotiationsBySale,
+    NegotiationsBySaleInner { sale_hash: CryptoHash },
+    RelayedNftContracts,
 }
 
 #[near_bindgen]
@@ -81,11 +212,38 @@ impl Contract {
         let mut this = Self {
             owner_id: owner_id.into(),
             sales: UnorderedMap::new(StorageKey::Sales),
+            offers: UnorderedMap::new(StorageKey::Offers),
+            offer_counts: LookupMap::new(StorageKey::OfferCounts),
+            max_offers_per_sale: DEFAULT_MAX_OFFERS_PER_SALE,
+            min_offer_principals: LookupMap::new(StorageKey::MinOfferPrincipals),
+            bundle_items: LookupMap::new(StorageKey::BundleItems),
+            bundled_in: LookupMap::new(StorageKey::BundledIn),
+            deposits: LookupMap::new(StorageKey::Deposits),
+            currency_stats: LookupMap::new(StorageKey::CurrencyStats),
+            collection_stats: UnorderedMap::new(StorageKey::CollectionStats),
+            reputations: LookupMap::new(StorageKey::Reputations),
+            referral_fee_rate: 0,
+            referral_rewards: LookupMap::new(StorageKey::ReferralRewards),
+            reward_token_id: None,
+            reward_rates: LookupMap::new(StorageKey::RewardRates),
+            reward_pool: 0,
+            rewards: LookupMap::new(StorageKey::Rewards),
+            stakes: LookupMap::new(StorageKey::Stakes),
+            fee_tiers: vec![],
+            rate_models: LookupMap::new(StorageKey::RateModels),
+            rate_pools: LookupMap::new(StorageKey::RatePools),
             by_owner_id: LookupMap::new(StorageKey::ByOwnerId),
             by_nft_contract_id: LookupMap::new(StorageKey::ByNFTContractId),
             by_nft_token_type: LookupMap::new(StorageKey::ByNFTTokenType),
             ft_token_ids: UnorderedSet::new(StorageKey::FTTokenIds),
             storage_deposits: LookupMap::new(StorageKey::StorageDeposits),
+            protocol_fees: LookupMap::new(StorageKey::ProtocolFees),
+            liquidation_bounty_rate: 0,
+            note_contract_id: None,
+            negotiations: UnorderedMap::new(StorageKey::Negotiations),
+            negotiations_by_sale: LookupMap::new(StorageKey::NegotiationsBySale),
+            next_negotiation_id: 0,
+            relayed_nft_contracts: LookupMap::new(StorageKey::RelayedNftContracts),
         };
         // support NEAR by default
         this.ft_token_ids.insert(&"near".to_string());
@@ -107,6 +265,48 @@ impl Contract {
         added
     }
 
+    /// only owner, basis points of the loan principal paid to a keeper liquidating someone else's loan
+    pub fn set_liquidation_bounty_rate(&mut self, liquidation_bounty_rate: u32) {
+        self.assert_owner();
+        assert!(
+            liquidation_bounty_rate <= 10000,
+            "liquidation_bounty_rate must be at most 10000"
+        );
+        self.liquidation_bounty_rate = liquidation_bounty_rate;
+    }
+
+    /// only owner
+    pub fn set_max_offers_per_sale(&mut self, max_offers_per_sale: u32) {
+        self.assert_owner();
+        assert!(max_offers_per_sale > 0, "max_offers_per_sale must be positive");
+        self.max_offers_per_sale = max_offers_per_sale;
+    }
+
+    /// only owner, smallest principal an offer in `ft_token_id` may carry
+    pub fn set_min_offer_principal(&mut self, ft_token_id: AccountId, amount: U128) {
+        self.assert_owner();
+        assert!(
+            self.ft_token_ids.contains(&ft_token_id),
+            "Token {} is not supported",
+            ft_token_id
+        );
+        self.min_offer_principals.insert(&ft_token_id, &amount.0);
+    }
+
+    /// only owner
+    pub fn withdraw_protocol_fees(&mut self, ft_token_id: AccountId, amount: U128) -> Promise {
+        self.assert_owner();
+        let balance = self.protocol_fees.get(&ft_token_id).unwrap_or(0);
+        assert!(
+            amount.0 <= balance,
+            "Insufficient protocol fees: {}, requested {}",
+            balance,
+            amount.0
+        );
+        self.protocol_fees.insert(&ft_token_id, &(balance - amount.0));
+        self.internal_send(&ft_token_id, self.owner_id.clone(), amount.0, "protocol fees")
+    }
+
     /// TODO remove token (should check if sales can complete even if owner stops supporting token type)
 
     #[payable]
@@ -159,10 +359,38 @@ impl Contract {
         U128(STORAGE_PER_SALE)
     }
 
+    pub fn storage_per_offer(&self) -> U128 {
+        U128(STORAGE_PER_OFFER)
+    }
+
+    /// (per negotiation, per step of its history)
+    pub fn storage_per_negotiation(&self) -> (U128, U128) {
+        (
+            U128(STORAGE_PER_NEGOTIATION),
+            U128(STORAGE_PER_NEGOTIATION_STEP),
+        )
+    }
+
     pub fn storage_balance_of(&self, account_id: ValidAccountId) -> U128 {
         U128(self.storage_deposits.get(account_id.as_ref()).unwrap_or(0))
     }
 
+    pub fn get_liquidation_bounty_rate(&self) -> u32 {
+        self.liquidation_bounty_rate
+    }
+
+    pub fn get_protocol_fees(&self, ft_token_id: AccountId) -> U128 {
+        U128(self.protocol_fees.get(&ft_token_id).unwrap_or(0))
+    }
+
+    pub fn get_max_offers_per_sale(&self) -> u32 {
+        self.max_offers_per_sale
+    }
+
+    pub fn get_min_offer_principal(&self, ft_token_id: AccountId) -> U128 {
+        U128(self.min_offer_principals.get(&ft_token_id).unwrap_or(0))
+    }
+
     /// deprecated
 
     pub fn storage_paid(&self, account_id: ValidAccountId) -> U128 {
@@ -173,3 +401,6 @@ impl Contract {
         U128(STORAGE_PER_SALE)
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,175 @@
+use crate::*;
+
+/// upgrade from the first deployed layout, where each Sale carried its offers and the contract
+/// kept no offers, stats or settings of its own. migrate rewrites every sale in one call, its
+/// offers move to Contract.offers under their old offer_id
+
+#[derive(BorshDeserialize)]
+pub struct OldOffer {
+    pub offer_id: u32,
+    pub lender_id: AccountId,
+    pub loan_principal_amount: u128,
+    pub loan_duration: u32,
+    pub loan_interest_rate: u32,
+    pub available_at: u64,
+    pub status: u32,
+    pub created_at: U64,
+    pub updated_at: U64,
+    pub started_at: U64,
+}
+
+#[derive(BorshDeserialize)]
+pub struct OldSale {
+    pub owner_id: AccountId,
+    pub approval_id: u64,
+    pub nft_contract_id: String,
+    pub token_id: String,
+    pub loan_principal_amount: u128,
+    pub loan_duration: u32,
+    pub loan_currency: TokenId,
+    pub loan_interest_rate: u32,
+    pub loan_config: u32,
+    pub available_at: u64,
+    pub status: u32,
+    pub lender: AccountId,
+    pub created_at: U64,
+    pub updated_at: U64,
+    pub offers: Vec<OldOffer>,
+    pub started_at: U64,
+}
+
+#[derive(BorshDeserialize)]
+pub struct OldContract {
+    pub owner_id: AccountId,
+    pub sales: UnorderedMap<ContractAndTokenId, OldSale>,
+    pub by_owner_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
+    pub by_nft_contract_id: LookupMap<AccountId, UnorderedSet<TokenId>>,
+    pub by_nft_token_type: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
+    pub ft_token_ids: UnorderedSet<AccountId>,
+    pub storage_deposits: LookupMap<AccountId, Balance>,
+}
+
+impl From<OldOffer> for Offer {
+    fn from(offer: OldOffer) -> Self {
+        Offer {
+            offer_id: offer.offer_id,
+            lender_id: offer.lender_id,
+            loan_principal_amount: offer.loan_principal_amount,
+            loan_duration: offer.loan_duration,
+            loan_interest_rate: offer.loan_interest_rate,
+            available_at: offer.available_at,
+            status: offer.status,
+            created_at: offer.created_at,
+            updated_at: offer.updated_at,
+            started_at: offer.started_at,
+            renewable: false,
+            min_on_time_rate: 0,
+            referrer_id: None,
+            variable_rate: false,
+            rate_index: U128(0),
+            allowed_borrower: None,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// only the contract itself, right after deploying over the old layout. Offers made
+    /// before it never paid STORAGE_PER_OFFER, settling one credits it to its lender once
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let mut old: OldContract = env::state_read().expect("No state to migrate");
+        let old_sales = old.sales.to_vec();
+        old.sales.clear();
+
+        let mut this = Self {
+            owner_id: old.owner_id,
+            sales: UnorderedMap::new(StorageKey::Sales),
+            offers: UnorderedMap::new(StorageKey::Offers),
+            offer_counts: LookupMap::new(StorageKey::OfferCounts),
+            max_offers_per_sale: DEFAULT_MAX_OFFERS_PER_SALE,
+            min_offer_principals: LookupMap::new(StorageKey::MinOfferPrincipals),
+            bundle_items: LookupMap::new(StorageKey::BundleItems),
+            bundled_in: LookupMap::new(StorageKey::BundledIn),
+            deposits: LookupMap::new(StorageKey::Deposits),
+            currency_stats: LookupMap::new(StorageKey::CurrencyStats),
+            collection_stats: UnorderedMap::new(StorageKey::CollectionStats),
+            reputations: LookupMap::new(StorageKey::Reputations),
+            referral_fee_rate: 0,
+            referral_rewards: LookupMap::new(StorageKey::ReferralRewards),
+            reward_token_id: None,
+            reward_rates: LookupMap::new(StorageKey::RewardRates),
+            reward_pool: 0,
+            rewards: LookupMap::new(StorageKey::Rewards),
+            stakes: LookupMap::new(StorageKey::Stakes),
+            fee_tiers: vec![],
+            rate_models: LookupMap::new(StorageKey::RateModels),
+            rate_pools: LookupMap::new(StorageKey::RatePools),
+            by_owner_id: old.by_owner_id,
+            by_nft_contract_id: old.by_nft_contract_id,
+            by_nft_token_type: old.by_nft_token_type,
+            ft_token_ids: old.ft_token_ids,
+            storage_deposits: old.storage_deposits,
+            protocol_fees: LookupMap::new(StorageKey::ProtocolFees),
+            liquidation_bounty_rate: 0,
+            note_contract_id: None,
+            negotiations: UnorderedMap::new(StorageKey::Negotiations),
+            negotiations_by_sale: LookupMap::new(StorageKey::NegotiationsBySale),
+            next_negotiation_id: 0,
+            relayed_nft_contracts: LookupMap::new(StorageKey::RelayedNftContracts),
+        };
+
+        for (contract_and_token_id, old_sale) in old_sales {
+            let offer_count = old_sale.offers.len() as u32;
+            for offer in old_sale.offers {
+                let offer: Offer = offer.into();
+                this.offers
+                    .insert(&offer_key(&contract_and_token_id, offer.offer_id), &offer);
+            }
+            if offer_count > 0 {
+                this.offer_counts
+                    .insert(&contract_and_token_id, &offer_count);
+            }
+            let sale = Sale {
+                owner_id: old_sale.owner_id,
+                approval_id: old_sale.approval_id,
+                nft_contract_id: old_sale.nft_contract_id,
+                token_id: old_sale.token_id,
+                loan_principal_amount: old_sale.loan_principal_amount,
+                loan_duration: old_sale.loan_duration,
+                loan_currency: old_sale.loan_currency,
+                loan_interest_rate: old_sale.loan_interest_rate,
+                loan_config: old_sale.loan_config,
+                obligation_receipt: false,
+                note_contract_id: None,
+                escrowed: false,
+                verified_at: old_sale.updated_at,
+                auto_renew: false,
+                auto_repay: false,
+                called_at: U64(0),
+                referrer_id: None,
+                allowed_lenders: vec![],
+                available_at: old_sale.available_at,
+                status: old_sale.status,
+                lender: old_sale.lender,
+                created_at: old_sale.created_at,
+                updated_at: old_sale.updated_at,
+                started_at: old_sale.started_at,
+            };
+            this.sales.insert(&contract_and_token_id, &sale);
+            // stats start from the sales still listed or running
+            this.internal_update_stats(&sale, |stats| {
+                if sale.status == LoanStatus::Open as u32 {
+                    stats.listed += 1;
+                    stats.open += 1;
+                } else if sale.status == LoanStatus::Processing as u32 {
+                    stats.listed += 1;
+                    stats.active += 1;
+                    stats.active_principal.0 += sale.loan_principal_amount;
+                }
+            });
+        }
+        this
+    }
+}
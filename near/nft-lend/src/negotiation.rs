@@ -0,0 +1,518 @@
+use crate::*;
+
+/// borrower counter-offers: the borrower answers an open Offer with other terms and both
+/// sides go back and forth until one accepts the other's latest terms or either rejects.
+/// The lender's latest terms are always kept on its Offer, a lender counter tops up or
+/// releases the escrowed principal, so accepting only has to start the loan.
+/// An offer has at most one open negotiation and negotiations only live as long as the
+/// listing: they are dropped with its offers when the NFT is listed again, or by
+/// clear_negotiations once the listing is over. Each negotiation and each step of its
+/// history is paid from the storage deposit of the account that adds it
+
+/// Negotiation.status and NegotiationStep.status
+pub enum NegotiationStatus {
+    Proposed = 0,
+    Accepted = 1,
+    Rejected = 2,
+    /// only in views, a proposal nobody answered before expires_at
+    Expired = 3,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NegotiationStep {
+    pub account_id: AccountId,
+    pub status: u32,
+    pub loan_principal_amount: u128,
+    pub loan_duration: u32,
+    pub loan_interest_rate: u32,
+    pub created_at: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Negotiation {
+    pub negotiation_id: U64,
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub offer_id: u32,
+    pub borrower_id: AccountId,
+    pub lender_id: AccountId,
+    /// latest proposed terms
+    pub loan_principal_amount: u128,
+    pub loan_duration: u32,
+    pub loan_interest_rate: u32,
+    pub proposed_by: AccountId,
+    pub expires_at: U64,
+    pub status: u32,
+    pub history: Vec<NegotiationStep>,
+}
+
+impl Negotiation {
+    /// the caller charges STORAGE_PER_NEGOTIATION_STEP to `account_id`
+    fn push_step(&mut self, account_id: AccountId, status: NegotiationStatus) {
+        self.history.push(NegotiationStep {
+            account_id,
+            status: status as u32,
+            loan_principal_amount: self.loan_principal_amount,
+            loan_duration: self.loan_duration,
+            loan_interest_rate: self.loan_interest_rate,
+            created_at: U64(env::block_timestamp() / 1000000000),
+        });
+    }
+
+    fn propose(
+        &mut self,
+        account_id: AccountId,
+        loan_principal_amount: u128,
+        loan_duration: u32,
+        loan_interest_rate: u32,
+        expires_in: u64,
+    ) {
+        assert!(loan_principal_amount > 0, "Amount must be greater than 0");
+        self.loan_principal_amount = loan_principal_amount;
+        self.loan_duration = loan_duration;
+        self.loan_interest_rate = loan_interest_rate;
+        self.proposed_by = account_id.clone();
+        self.expires_at = U64(env::block_timestamp() / 1000000000 + expires_in);
+        self.push_step(account_id, NegotiationStatus::Proposed);
+    }
+
+    /// status as seen now, an unanswered proposal past expires_at is Expired
+    fn current(mut self) -> Self {
+        if self.status == NegotiationStatus::Proposed as u32
+            && self.expires_at.0 < env::block_timestamp() / 1000000000
+        {
+            self.status = NegotiationStatus::Expired as u32;
+        }
+        self
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// borrower answers an open offer with other terms, returns the negotiation id
+    pub fn counter_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        offer_id: u32,
+        loan_principal_amount: U128,
+        loan_duration: u32,
+        loan_interest_rate: u32,
+        expires_in: U64,
+    ) -> U64 {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert_borrower(&sale);
+        let borrower_id = user_account_id();
+        assert!(
+            sale.status == LoanStatus::Open as u32,
+            "Loan is in proccessing "
+        );
+        assert!(
+            sale.loan_config != LoanConfig::Tranched as u32,
+            "Tranched sale must be accepted with accept_tranches"
+        );
+        let offer = self
+            .internal_get_offer(&contract_and_token_id, offer_id)
+            .filter(|offer| offer.status == LoanStatus::Open as u32)
+            .expect("Offer is not open");
+        // a second thread could rewrite the terms the first one is about to accept
+        if let Some(open) = self
+            .get_negotiations_by_sale(nft_contract_id.clone(), token_id.clone())
+            .into_iter()
+            .find(|negotiation| {
+                negotiation.offer_id == offer_id
+                    && negotiation.status == NegotiationStatus::Proposed as u32
+            })
+        {
+            env::panic(
+                format!(
+                    "Offer {} is already in negotiation {}",
+                    offer_id, open.negotiation_id.0
+                )
+                .as_bytes(),
+            );
+        }
+        self.internal_charge_storage(
+            &borrower_id,
+            STORAGE_PER_NEGOTIATION + STORAGE_PER_NEGOTIATION_STEP,
+        );
+
+        let negotiation_id = self.next_negotiation_id;
+        self.next_negotiation_id += 1;
+        let mut negotiation = Negotiation {
+            negotiation_id: U64(negotiation_id),
+            nft_contract_id,
+            token_id,
+            offer_id,
+            borrower_id: borrower_id.clone(),
+            lender_id: offer.lender_id.clone(),
+            loan_principal_amount: 0,
+            loan_duration: 0,
+            loan_interest_rate: 0,
+            proposed_by: borrower_id.clone(),
+            expires_at: U64(0),
+            status: NegotiationStatus::Proposed as u32,
+            history: vec![],
+        };
+        negotiation.propose(
+            borrower_id,
+            loan_principal_amount.0,
+            loan_duration,
+            loan_interest_rate,
+            expires_in.0,
+        );
+        self.negotiations.insert(&negotiation_id, &negotiation);
+
+        let mut by_sale = self
+            .negotiations_by_sale
+            .get(&contract_and_token_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::NegotiationsBySaleInner {
+                        sale_hash: hash_account_id(&contract_and_token_id),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        by_sale.insert(&negotiation_id);
+        self.negotiations_by_sale
+            .insert(&contract_and_token_id, &by_sale);
+        U64(negotiation_id)
+    }
+
+    /// answers the other side's latest terms with new ones, a lender attaches NEAR to
+    /// raise the principal, for ft currencies see ft_on_transfer "counter_negotiation"
+    #[payable]
+    pub fn counter_negotiation(
+        &mut self,
+        negotiation_id: U64,
+        loan_principal_amount: U128,
+        loan_duration: u32,
+        loan_interest_rate: u32,
+        expires_in: U64,
+    ) {
+        let account_id = user_account_id();
+        let refund = self.internal_counter_negotiation(
+            negotiation_id.0,
+            account_id.clone(),
+            &"near".to_string(),
+            env::attached_deposit(),
+            loan_principal_amount.0,
+            loan_duration,
+            loan_interest_rate,
+            expires_in.0,
+        );
+        if refund > 0 {
+            Promise::new(account_id).transfer(refund);
+        }
+    }
+
+    /// accepts the other side's latest terms and starts the loan, a lender attaches NEAR
+    /// if the principal went up, for ft currencies see ft_on_transfer "accept_negotiation"
+    #[payable]
+    pub fn accept_negotiation(&mut self, negotiation_id: U64) {
+        let account_id = user_account_id();
+        let refund = self.internal_accept_negotiation(
+            negotiation_id.0,
+            account_id.clone(),
+            &"near".to_string(),
+            env::attached_deposit(),
+        );
+        if refund > 0 {
+            Promise::new(account_id).transfer(refund);
+        }
+    }
+
+    /// either side ends the negotiation, the Offer stays as the lender last set it
+    pub fn reject_negotiation(&mut self, negotiation_id: U64) {
+        let account_id = user_account_id();
+        let mut negotiation = self
+            .negotiations
+            .get(&negotiation_id.0)
+            .expect("No negotiation");
+        assert!(
+            account_id == negotiation.borrower_id || account_id == negotiation.lender_id,
+            "Not a party of this negotiation"
+        );
+        assert!(
+            negotiation.status == NegotiationStatus::Proposed as u32,
+            "Negotiation is closed"
+        );
+        self.internal_charge_storage(&account_id, STORAGE_PER_NEGOTIATION_STEP);
+        negotiation.status = NegotiationStatus::Rejected as u32;
+        negotiation.push_step(account_id, NegotiationStatus::Rejected);
+        self.negotiations.insert(&negotiation_id.0, &negotiation);
+    }
+
+    /// anyone can drop the negotiations of a listing that is no longer open, their
+    /// storage goes back to the accounts that paid it
+    pub fn clear_negotiations(&mut self, nft_contract_id: AccountId, token_id: TokenId) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        if let Some(sale) = self.sales.get(&contract_and_token_id) {
+            assert!(
+                sale.status != LoanStatus::Open as u32,
+                "Listing is still open"
+            );
+        }
+        self.internal_clear_negotiations(&contract_and_token_id);
+    }
+
+    /// views
+
+    pub fn get_negotiation(&self, negotiation_id: U64) -> Option<Negotiation> {
+        self.negotiations
+            .get(&negotiation_id.0)
+            .map(|negotiation| negotiation.current())
+    }
+
+    pub fn get_negotiations_by_sale(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+    ) -> Vec<Negotiation> {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        match self.negotiations_by_sale.get(&contract_and_token_id) {
+            Some(by_sale) => by_sale
+                .iter()
+                .filter_map(|negotiation_id| self.negotiations.get(&negotiation_id))
+                .map(|negotiation| negotiation.current())
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+impl Contract {
+    /// returns the part of `deposit` that was not needed
+    pub(crate) fn internal_counter_negotiation(
+        &mut self,
+        negotiation_id: u64,
+        account_id: AccountId,
+        ft_token_id: &AccountId,
+        deposit: u128,
+        loan_principal_amount: u128,
+        loan_duration: u32,
+        loan_interest_rate: u32,
+        expires_in: u64,
+    ) -> u128 {
+        let (mut negotiation, sale) = self.internal_negotiation_turn(negotiation_id, &account_id);
+        self.internal_charge_storage(&account_id, STORAGE_PER_NEGOTIATION_STEP);
+        let refund = if account_id == negotiation.lender_id {
+            self.internal_update_lender_offer(
+                &negotiation,
+                sale,
+                ft_token_id,
+                deposit,
+                loan_principal_amount,
+                loan_duration,
+                loan_interest_rate,
+            )
+        } else {
+            deposit
+        };
+        negotiation.propose(
+            account_id,
+            loan_principal_amount,
+            loan_duration,
+            loan_interest_rate,
+            expires_in,
+        );
+        self.negotiations.insert(&negotiation_id, &negotiation);
+        refund
+    }
+
+    /// returns the part of `deposit` that was not needed
+    pub(crate) fn internal_accept_negotiation(
+        &mut self,
+        negotiation_id: u64,
+        account_id: AccountId,
+        ft_token_id: &AccountId,
+        deposit: u128,
+    ) -> u128 {
+        let (mut negotiation, sale) = self.internal_negotiation_turn(negotiation_id, &account_id);
+        self.internal_charge_storage(&account_id, STORAGE_PER_NEGOTIATION_STEP);
+        let contract_and_token_id =
+            format!("{}{}{}", sale.nft_contract_id, DELIMETER, sale.token_id);
+        // a borrower accepts terms that already are on the Offer
+        let refund = if account_id == negotiation.lender_id {
+            self.internal_update_lender_offer(
+                &negotiation,
+                sale,
+                ft_token_id,
+                deposit,
+                negotiation.loan_principal_amount,
+                negotiation.loan_duration,
+                negotiation.loan_interest_rate,
+            )
+        } else {
+            deposit
+        };
+        let offer = self
+            .internal_get_offer(&contract_and_token_id, negotiation.offer_id)
+            .expect("No offer");
+        assert!(
+            offer.loan_principal_amount == negotiation.loan_principal_amount
+                && offer.loan_duration == negotiation.loan_duration
+                && offer.loan_interest_rate == negotiation.loan_interest_rate,
+            "Offer terms are not the negotiated terms"
+        );
+        negotiation.status = NegotiationStatus::Accepted as u32;
+        negotiation.push_step(account_id, NegotiationStatus::Accepted);
+        self.negotiations.insert(&negotiation_id, &negotiation);
+        self.internal_accept_offer(
+            negotiation.nft_contract_id,
+            negotiation.token_id,
+            negotiation.offer_id,
+        );
+        refund
+    }
+
+    /// an open negotiation waiting for `account_id`, on a sale and offer that are still open
+    fn internal_negotiation_turn(
+        &self,
+        negotiation_id: u64,
+        account_id: &AccountId,
+    ) -> (Negotiation, Sale) {
+        let negotiation = self
+            .negotiations
+            .get(&negotiation_id)
+            .expect("No negotiation")
+            .current();
+        assert!(
+            account_id == &negotiation.borrower_id || account_id == &negotiation.lender_id,
+            "Not a party of this negotiation"
+        );
+        assert!(
+            negotiation.status == NegotiationStatus::Proposed as u32,
+            "Negotiation is closed"
+        );
+        assert!(
+            account_id != &negotiation.proposed_by,
+            "Waiting for the other party"
+        );
+        let contract_and_token_id = format!(
+            "{}{}{}",
+            negotiation.nft_contract_id, DELIMETER, negotiation.token_id
+        );
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert!(
+            sale.status == LoanStatus::Open as u32,
+            "Loan is in proccessing "
+        );
+        assert!(
+            sale.owner_id == negotiation.borrower_id,
+            "invalid owner owner's loan:{}, negotiation's borrower:{}",
+            sale.owner_id,
+            negotiation.borrower_id,
+        );
+        let offer = self
+            .internal_get_offer(&contract_and_token_id, negotiation.offer_id)
+            .filter(|offer| offer.status == LoanStatus::Open as u32)
+            .expect("Offer is not open");
+        assert!(
+            offer.lender_id == negotiation.lender_id,
+            "invalid lender offer's lender:{}, negotiation's lender:{}",
+            offer.lender_id,
+            negotiation.lender_id,
+        );
+        (negotiation, sale)
+    }
+
+    /// takes `amount` from the storage deposit of `account_id`, its listings keep theirs
+    fn internal_charge_storage(&mut self, account_id: &AccountId, amount: Balance) {
+        let balance = self.storage_deposits.get(account_id).unwrap_or(0);
+        let listings = self
+            .by_owner_id
+            .get(account_id)
+            .map(|s| s.len())
+            .unwrap_or_default();
+        let required = amount + u128::from(listings) * STORAGE_PER_SALE;
+        assert!(
+            balance >= required,
+            "Insufficient storage paid: {}, this requires {}",
+            balance,
+            required
+        );
+        self.storage_deposits
+            .insert(account_id, &(balance - amount));
+    }
+
+    /// drops every negotiation of a listing and gives their storage back
+    pub(crate) fn internal_clear_negotiations(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+    ) {
+        let mut by_sale = match self.negotiations_by_sale.remove(contract_and_token_id) {
+            Some(by_sale) => by_sale,
+            None => return,
+        };
+        for negotiation_id in by_sale.to_vec() {
+            if let Some(negotiation) = self.negotiations.remove(&negotiation_id) {
+                self.internal_release_storage(&negotiation.borrower_id, STORAGE_PER_NEGOTIATION);
+                for step in negotiation.history {
+                    self.internal_release_storage(&step.account_id, STORAGE_PER_NEGOTIATION_STEP);
+                }
+            }
+        }
+        by_sale.clear();
+    }
+
+    fn internal_release_storage(&mut self, account_id: &AccountId, amount: Balance) {
+        let balance = self.storage_deposits.get(account_id).unwrap_or(0);
+        self.storage_deposits
+            .insert(account_id, &(balance + amount));
+    }
+
+    /// puts the lender's terms on its Offer, `deposit` tops up the escrowed principal and
+    /// a lower principal is released to the lender. Returns the unused part of `deposit`
+    fn internal_update_lender_offer(
+        &mut self,
+        negotiation: &Negotiation,
+        sale: Sale,
+        ft_token_id: &AccountId,
+        deposit: u128,
+        loan_principal_amount: u128,
+        loan_duration: u32,
+        loan_interest_rate: u32,
+    ) -> u128 {
+        assert!(
+            deposit == 0 || ft_token_id == &sale.loan_currency,
+            "ft_token is invalid"
+        );
+        let contract_and_token_id =
+            format!("{}{}{}", sale.nft_contract_id, DELIMETER, sale.token_id);
+        let mut offer = self
+            .internal_get_offer(&contract_and_token_id, negotiation.offer_id)
+            .expect("Offer is not open");
+        self.assert_min_offer_principal(&sale.loan_currency, loan_principal_amount);
+        self.assert_offer_terms(&sale, loan_principal_amount, loan_duration);
+        let escrowed = offer.loan_principal_amount;
+        let refund = if loan_principal_amount >= escrowed {
+            let top_up = loan_principal_amount - escrowed;
+            assert!(
+                deposit >= top_up,
+                "Deposit {} more to cover the principal",
+                top_up
+            );
+            deposit - top_up
+        } else {
+            self.internal_send(
+                &sale.loan_currency,
+                offer.lender_id.clone(),
+                escrowed - loan_principal_amount,
+                "refund from market",
+            );
+            deposit
+        };
+        offer.loan_principal_amount = loan_principal_amount;
+        offer.loan_duration = loan_duration;
+        offer.loan_interest_rate = loan_interest_rate;
+        offer.updated_at = U64(env::block_timestamp() / 1000000000);
+        self.internal_update_offer(&contract_and_token_id, &offer);
+        refund
+    }
+}
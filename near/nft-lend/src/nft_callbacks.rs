@@ -1,6 +1,6 @@
 use crate::*;
 
-/// approval callbacks from NFT Contracts
+/// approval and transfer callbacks from NFT Contracts
 
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
@@ -11,6 +11,21 @@ pub struct SaleArgs {
     pub loan_interest_rate: u32,
     pub loan_config: u32,
     pub available_at: u64,
+    pub obligation_receipt: Option<bool>,
+    /// front-end that brought the listing, see referral.rs
+    pub referrer_id: Option<AccountId>,
+    /// only these lenders can fund the sale, see counterparty.rs
+    pub allowed_lenders: Option<Vec<AccountId>>,
+}
+
+trait NonFungibleTokenReceiver {
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool>;
 }
 
 trait NonFungibleTokenApprovalsReceiver {
@@ -34,26 +49,135 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
         approval_id: u64,
         msg: String,
     ) {
-        // enforce cross contract call and owner_id is signer
+        // enforce cross contract call and owner_id is signer, or the relayer signed for an
+        // NFT contract the owner allowed with set_relayed_nft_contracts
 
         let nft_contract_id = env::predecessor_account_id();
-        let signer_id = env::signer_account_id();
         assert_ne!(
-            nft_contract_id, signer_id,
+            nft_contract_id,
+            env::signer_account_id(),
             "nft_on_approve should only be called via cross-contract call"
         );
+        self.assert_nft_owner_signed(&nft_contract_id, owner_id.as_ref());
+
+        // msg is BundleArgs for an item added to an existing sale, SaleArgs otherwise
+        if let Ok(args) = near_sdk::serde_json::from_str::<BundleArgs>(&msg) {
+            self.internal_add_bundle_item(
+                nft_contract_id,
+                token_id,
+                owner_id.into(),
+                approval_id,
+                args,
+            );
+        } else {
+            self.internal_add_sale(
+                nft_contract_id,
+                token_id,
+                owner_id.into(),
+                approval_id,
+                &msg,
+                false,
+            );
+        }
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenReceiver for Contract {
+    /// lists a token deposited with nft_transfer_call, msg is SaleArgs. The NFT stays in
+    /// escrow until the loan is paid back, liquidated or cancelled
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        let nft_contract_id = env::predecessor_account_id();
+        assert_ne!(
+            nft_contract_id,
+            env::signer_account_id(),
+            "nft_on_transfer should only be called via cross-contract call"
+        );
         assert_eq!(
-            owner_id.as_ref(),
-            &signer_id,
-            "owner_id should be signer_id"
+            sender_id, previous_owner_id,
+            "Only the token owner can list it"
         );
+        self.assert_nft_owner_signed(&nft_contract_id, &previous_owner_id);
+        self.internal_add_sale(nft_contract_id, token_id, previous_owner_id, 0, &msg, true);
+        // keep the token
+        PromiseOrValue::Value(false)
+    }
+}
 
-        // enforce signer's storage is enough to cover + 1 more sale
+#[near_bindgen]
+impl Contract {
+    /// NFT contracts that may list the caller's tokens when a relayer signs the transaction
+    /// (NEP-366 delegate actions), replaces the previous list. Requires a storage deposit
+    pub fn set_relayed_nft_contracts(&mut self, nft_contract_ids: Vec<ValidAccountId>) {
+        let account_id = user_account_id();
+        assert!(
+            self.storage_deposits.get(&account_id).unwrap_or(0) >= STORAGE_PER_SALE,
+            "Requires a storage deposit of {}",
+            STORAGE_PER_SALE
+        );
+        assert!(
+            nft_contract_ids.len() <= MAX_RELAYED_NFT_CONTRACTS,
+            "At most {} relayed NFT contracts",
+            MAX_RELAYED_NFT_CONTRACTS
+        );
+        if nft_contract_ids.is_empty() {
+            self.relayed_nft_contracts.remove(&account_id);
+        } else {
+            let nft_contract_ids: Vec<AccountId> =
+                nft_contract_ids.into_iter().map(|id| id.into()).collect();
+            self.relayed_nft_contracts
+                .insert(&account_id, &nft_contract_ids);
+        }
+    }
+
+    /// views
+
+    pub fn get_relayed_nft_contracts(&self, account_id: AccountId) -> Vec<AccountId> {
+        self.relayed_nft_contracts
+            .get(&account_id)
+            .unwrap_or_default()
+    }
+}
+
+impl Contract {
+    /// an NFT contract can claim any owner_id, the owner must have signed the transaction or
+    /// allowed the contract for relayed listings
+    fn assert_nft_owner_signed(&self, nft_contract_id: &AccountId, owner_id: &AccountId) {
+        if owner_id == &env::signer_account_id() {
+            return;
+        }
+        let allowed = self
+            .relayed_nft_contracts
+            .get(owner_id)
+            .map_or(false, |ids| ids.contains(nft_contract_id));
+        assert!(
+            allowed,
+            "owner_id should be signer_id, or allow relayed listings from {}",
+            nft_contract_id
+        );
+    }
+
+    pub(crate) fn internal_add_sale(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+        msg: &str,
+        escrowed: bool,
+    ) {
+        // enforce owner's storage is enough to cover + 1 more sale
 
         let storage_amount = self.storage_amount().0;
-        let owner_paid_storage = self.storage_deposits.get(&signer_id).unwrap_or(0);
+        let owner_paid_storage = self.storage_deposits.get(&owner_id).unwrap_or(0);
         let signer_storage_required =
-            (self.get_supply_by_owner_id(signer_id).0 + 1) as u128 * storage_amount;
+            (self.get_supply_by_owner_id(owner_id.clone()).0 + 1) as u128 * storage_amount;
         assert!(
             owner_paid_storage >= signer_storage_required,
             "Insufficient storage paid: {}, for {} sales at {} rate of per sale",
@@ -69,7 +193,21 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
             loan_interest_rate,
             loan_config,
             available_at,
-        } = near_sdk::serde_json::from_str(&msg).expect("Not valid SaleArgs");
+            obligation_receipt,
+            referrer_id,
+            allowed_lenders,
+        } = near_sdk::serde_json::from_str(msg).expect("Not valid SaleArgs");
+
+        assert!(
+            loan_config != LoanConfig::Callable as u32 || loan_duration >= MIN_CALL_NOTICE,
+            "Call notice must be at least {} seconds",
+            MIN_CALL_NOTICE
+        );
+        assert!(
+            referrer_id.as_ref() != Some(&owner_id),
+            "{} cannot refer its own loan",
+            owner_id
+        );
 
         let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
 
@@ -85,36 +223,54 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
             }
         }
 
-        let offers: Vec<Offer> = Vec::new();
+        if let Some(bundle_of) = self.internal_live_bundle_of(&contract_and_token_id) {
+            env::panic(format!("NFT is already bundled in {}", bundle_of).as_bytes());
+        }
+
+        // offers, negotiations and items of an earlier listing of this NFT are dropped, open
+        // offers are refunded, offer ids start over
+        self.internal_clear_offers(&contract_and_token_id);
+        self.internal_clear_negotiations(&contract_and_token_id);
+        self.internal_clear_bundle(&contract_and_token_id);
         let lender = "";
-        self.sales.insert(
-            &contract_and_token_id,
-            &Sale {
-                owner_id: owner_id.clone().into(),
-                approval_id,
-                nft_contract_id: nft_contract_id.clone(),
-                token_id: token_id.clone(),
-                loan_principal_amount: loan_principal_amount.0,
-                loan_duration: loan_duration,
-                loan_currency: loan_currency,
-                loan_interest_rate: loan_interest_rate,
-                loan_config: loan_config,
-                available_at: available_at,
-                created_at: U64(env::block_timestamp() / 1000000000),
-                updated_at: U64(env::block_timestamp() / 1000000000),
-                started_at: U64(0),
-                status: LoanStatus::Open as u32,
-                lender: lender.to_string(),
-                offers: offers,
-            },
-        );
+        let sale = Sale {
+            owner_id: owner_id.clone(),
+            approval_id,
+            nft_contract_id: nft_contract_id.clone(),
+            token_id: token_id.clone(),
+            loan_principal_amount: loan_principal_amount.0,
+            loan_duration: loan_duration,
+            loan_currency: loan_currency,
+            loan_interest_rate: loan_interest_rate,
+            loan_config: loan_config,
+            obligation_receipt: obligation_receipt.unwrap_or(false),
+            note_contract_id: None,
+            escrowed,
+            verified_at: U64(env::block_timestamp() / 1000000000),
+            auto_renew: false,
+            auto_repay: false,
+            called_at: U64(0),
+            referrer_id,
+            allowed_lenders: allowed_lenders.unwrap_or_default(),
+            available_at: available_at,
+            created_at: U64(env::block_timestamp() / 1000000000),
+            updated_at: U64(env::block_timestamp() / 1000000000),
+            started_at: U64(0),
+            status: LoanStatus::Open as u32,
+            lender: lender.to_string(),
+        };
+        self.sales.insert(&contract_and_token_id, &sale);
+        self.internal_update_stats(&sale, |stats| {
+            stats.listed += 1;
+            stats.open += 1;
+        });
 
         // extra for views
 
-        let mut by_owner_id = self.by_owner_id.get(owner_id.as_ref()).unwrap_or_else(|| {
+        let mut by_owner_id = self.by_owner_id.get(&owner_id).unwrap_or_else(|| {
             UnorderedSet::new(
                 StorageKey::ByOwnerIdInner {
-                    account_id_hash: hash_account_id(owner_id.as_ref()),
+                    account_id_hash: hash_account_id(&owner_id),
                 }
                 .try_to_vec()
                 .unwrap(),
@@ -127,7 +283,7 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
             "User has more sales than storage paid"
         );
         by_owner_id.insert(&contract_and_token_id);
-        self.by_owner_id.insert(owner_id.as_ref(), &by_owner_id);
+        self.by_owner_id.insert(&owner_id, &by_owner_id);
 
         let mut by_nft_contract_id = self
             .by_nft_contract_id
@@ -0,0 +1,300 @@
+use crate::*;
+
+/// promissory notes: every lender position can be minted as an NFT on note_contract_id,
+/// repayments and collateral go to whoever holds the note when the loan settles.
+/// Borrowers can ask for an obligation receipt on the same contract, whoever holds it
+/// when the loan is paid back gets the NFT
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NoteMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+/// one note per funded offer, started_at keeps notes of a relisted NFT apart
+pub(crate) fn note_token_id(
+    nft_contract_id: &str,
+    token_id: &str,
+    started_at: u64,
+    offer_id: u32,
+) -> TokenId {
+    format!(
+        "{}{}{}{}{}{}{}",
+        nft_contract_id, DELIMETER, token_id, DELIMETER, started_at, DELIMETER, offer_id
+    )
+}
+
+/// the borrower side of a loan, one receipt per loan start
+pub(crate) fn receipt_token_id(nft_contract_id: &str, token_id: &str, started_at: u64) -> TokenId {
+    format!(
+        "{}{}{}{}{}{}receipt",
+        nft_contract_id, DELIMETER, token_id, DELIMETER, started_at, DELIMETER
+    )
+}
+
+#[near_bindgen]
+impl Contract {
+    /// only owner
+    pub fn set_note_contract_id(&mut self, note_contract_id: Option<ValidAccountId>) {
+        self.assert_owner();
+        self.note_contract_id = note_contract_id.map(|a| a.into());
+    }
+
+    #[private]
+    pub fn resolve_note_payout(
+        &mut self,
+        note_contract_id: AccountId,
+        note_token_id: TokenId,
+        lender_id: AccountId,
+        ft_token_id: AccountId,
+        amount: U128,
+        memo: String,
+    ) -> Promise {
+        match token_owner() {
+            Some(holder_id) => {
+                self.internal_burn_note(&note_contract_id, note_token_id);
+                self.internal_send(&ft_token_id, holder_id, amount.0, &memo)
+            }
+            None => self.internal_send(&ft_token_id, lender_id, amount.0, &memo),
+        }
+    }
+
+    #[private]
+    pub fn resolve_note_collateral(
+        &mut self,
+        note_contract_id: AccountId,
+        note_token_id: TokenId,
+        receiver_id: AccountId,
+        nft_contract_id: AccountId,
+        token_id: String,
+    ) -> Promise {
+        match token_owner() {
+            Some(holder_id) => {
+                self.internal_burn_note(&note_contract_id, note_token_id);
+                self.process_liquidate_loan(nft_contract_id, token_id, holder_id)
+            }
+            None => self.process_liquidate_loan(nft_contract_id, token_id, receiver_id),
+        }
+    }
+
+    /// views
+
+    pub fn get_note_contract_id(&self) -> Option<AccountId> {
+        self.note_contract_id.clone()
+    }
+
+    /// token id of the obligation receipt of a running loan on note_contract_id
+    pub fn get_obligation_receipt(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: String,
+    ) -> Option<TokenId> {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        if sale.status == LoanStatus::Processing as u32 {
+            self.internal_receipt_of(&sale)
+        } else {
+            None
+        }
+    }
+}
+
+impl Contract {
+    /// mints the note of a funded offer to `receiver_id`, no-op for a loan started without
+    /// notes
+    pub(crate) fn internal_mint_note(&self, sale: &Sale, offer: &Offer, receiver_id: AccountId) {
+        let note_contract_id = match &sale.note_contract_id {
+            Some(note_contract_id) => note_contract_id,
+            None => return,
+        };
+        // tranches carry their own principal and rate
+        let (principal, interest_rate) = if sale.loan_config == LoanConfig::Tranched as u32 {
+            (offer.loan_principal_amount, offer.loan_interest_rate)
+        } else {
+            (sale.loan_principal_amount, sale.loan_interest_rate)
+        };
+        ext_contract::nft_mint(
+            note_token_id(
+                &sale.nft_contract_id,
+                &sale.token_id,
+                sale.started_at.0,
+                offer.offer_id,
+            ),
+            NoteMetadata {
+                title: Some(format!(
+                    "Loan on {} {}",
+                    sale.nft_contract_id, sale.token_id
+                )),
+                description: Some(if offer.variable_rate {
+                    format!(
+                        "{} {} for {} seconds at a variable rate",
+                        principal, sale.loan_currency, sale.loan_duration
+                    )
+                } else {
+                    format!(
+                        "{} {} for {} seconds at {} bps",
+                        principal, sale.loan_currency, sale.loan_duration, interest_rate
+                    )
+                }),
+            },
+            receiver_id,
+            note_contract_id,
+            NO_DEPOSIT,
+            GAS_FOR_NOTE,
+        );
+    }
+
+    /// pays the position `offer_id` of the current term, to the note holder if a note was
+    /// minted for it
+    pub(crate) fn internal_pay_position(
+        &self,
+        sale: &Sale,
+        offer_id: u32,
+        lender_id: AccountId,
+        ft_token_id: AccountId,
+        amount: Balance,
+        memo: &str,
+    ) -> Promise {
+        match &sale.note_contract_id {
+            None => self.internal_send(&ft_token_id, lender_id, amount, memo),
+            Some(note_contract_id) => {
+                let note_token_id = note_token_id(
+                    &sale.nft_contract_id,
+                    &sale.token_id,
+                    sale.started_at.0,
+                    offer_id,
+                );
+                ext_contract::nft_token(
+                    note_token_id.clone(),
+                    note_contract_id,
+                    NO_DEPOSIT,
+                    GAS_FOR_NOTE,
+                )
+                .then(ext_self::resolve_note_payout(
+                    note_contract_id.clone(),
+                    note_token_id,
+                    lender_id,
+                    ft_token_id,
+                    U128(amount),
+                    memo.to_string(),
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_RESOLVE_NOTE,
+                ))
+            }
+        }
+    }
+
+    /// transfers the collateral of a defaulted position, to the note holder if there is one
+    pub(crate) fn internal_deliver_collateral(
+        &mut self,
+        sale: &Sale,
+        offer_id: u32,
+        lender_id: AccountId,
+    ) -> Promise {
+        let note = sale.note_contract_id.clone().map(|note_contract_id| {
+            (
+                note_contract_id,
+                note_token_id(
+                    &sale.nft_contract_id,
+                    &sale.token_id,
+                    sale.started_at.0,
+                    offer_id,
+                ),
+            )
+        });
+        self.internal_transfer_to_holder(
+            note,
+            lender_id,
+            sale.nft_contract_id.clone(),
+            sale.token_id.clone(),
+        )
+    }
+
+    /// the obligation receipt of a running loan, if the borrower asked for one
+    pub(crate) fn internal_receipt_of(&self, sale: &Sale) -> Option<TokenId> {
+        if sale.obligation_receipt && sale.note_contract_id.is_some() {
+            Some(receipt_token_id(
+                &sale.nft_contract_id,
+                &sale.token_id,
+                sale.started_at.0,
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// mints the obligation receipt of a loan that just started to its borrower
+    pub(crate) fn internal_mint_receipt(&self, sale: &Sale) {
+        if let Some(receipt_token_id) = self.internal_receipt_of(sale) {
+            ext_contract::nft_mint(
+                receipt_token_id,
+                NoteMetadata {
+                    title: Some(format!(
+                        "Obligation receipt for {} {}",
+                        sale.nft_contract_id, sale.token_id
+                    )),
+                    description: Some("Pay back the loan to receive the collateral".to_string()),
+                },
+                sale.owner_id.clone(),
+                sale.note_contract_id.as_ref().unwrap(),
+                NO_DEPOSIT,
+                GAS_FOR_NOTE,
+            );
+        }
+    }
+
+    /// transfers the collateral of a paid back loan, to the receipt holder if there is one
+    pub(crate) fn internal_return_collateral(
+        &mut self,
+        sale: &Sale,
+        borrower_id: AccountId,
+    ) -> Promise {
+        let receipt = self
+            .internal_receipt_of(sale)
+            .map(|receipt_token_id| (sale.note_contract_id.clone().unwrap(), receipt_token_id));
+        self.internal_transfer_to_holder(
+            receipt,
+            borrower_id,
+            sale.nft_contract_id.clone(),
+            sale.token_id.clone(),
+        )
+    }
+
+    /// nft_transfer to the holder of `note`, (note_contract_id, note_token_id), and burns it, to
+    /// `receiver_id` if it was never minted
+    fn internal_transfer_to_holder(
+        &mut self,
+        note: Option<(AccountId, TokenId)>,
+        receiver_id: AccountId,
+        nft_contract_id: AccountId,
+        token_id: String,
+    ) -> Promise {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let gas = GAS_FOR_RESOLVE_NOTE + self.internal_bundle_gas(&contract_and_token_id);
+        match note {
+            Some((note_contract_id, note_token_id)) => ext_contract::nft_token(
+                note_token_id.clone(),
+                &note_contract_id,
+                NO_DEPOSIT,
+                GAS_FOR_NOTE,
+            )
+            .then(ext_self::resolve_note_collateral(
+                note_contract_id.clone(),
+                note_token_id,
+                receiver_id,
+                nft_contract_id,
+                token_id,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                gas,
+            )),
+            None => self.process_liquidate_loan(nft_contract_id, token_id, receiver_id),
+        }
+    }
+
+    pub(crate) fn internal_burn_note(&self, note_contract_id: &AccountId, note_token_id: TokenId) {
+        ext_contract::nft_burn(note_token_id, note_contract_id, NO_DEPOSIT, GAS_FOR_NOTE);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::*;
+use near_sdk::promise_result_as_success;
+
+/// referral fee sharing: a listing (SaleArgs.referrer_id) and an offer (PurchaseArgs.referrer_id)
+/// can each name the front-end that brought them, each referrer is credited
+/// referral_fee_rate of the protocol fee its side of the loan pays
+
+pub(crate) fn referral_key(account_id: &str, ft_token_id: &str) -> String {
+    format!("{}{}{}", account_id, DELIMETER, ft_token_id)
+}
+
+/// a referrer is a third party, neither referrer of a loan can be its lender or borrower
+pub(crate) fn check_referrers(
+    sale: &Sale,
+    lender_id: &AccountId,
+    offer_referrer_id: Option<&AccountId>,
+) -> Result<(), String> {
+    for referrer_id in sale.referrer_id.iter().chain(offer_referrer_id) {
+        if referrer_id == lender_id || referrer_id == &sale.owner_id {
+            return Err(format!("{} cannot refer its own loan", referrer_id));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn assert_referrers(
+    sale: &Sale,
+    lender_id: &AccountId,
+    offer_referrer_id: Option<&AccountId>,
+) {
+    if let Err(message) = check_referrers(sale, lender_id, offer_referrer_id) {
+        env::panic(message.as_bytes());
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// only owner, basis points of the protocol fee credited to each referrer
+    pub fn set_referral_fee_rate(&mut self, referral_fee_rate: u32) {
+        self.assert_owner();
+        // a loan can have two referrers, the listing's and the offer's
+        assert!(
+            referral_fee_rate <= 5000,
+            "referral_fee_rate must be at most 5000"
+        );
+        self.referral_fee_rate = referral_fee_rate;
+    }
+
+    #[payable]
+    pub fn claim_referral_rewards(&mut self, ft_token_id: AccountId) -> Promise {
+        assert_one_yocto();
+        let referrer_id = env::predecessor_account_id();
+        let amount = self
+            .referral_rewards
+            .remove(&referral_key(&referrer_id, &ft_token_id))
+            .unwrap_or(0);
+        assert!(amount > 0, "No referral rewards");
+        self.internal_send(
+            &ft_token_id,
+            referrer_id.clone(),
+            amount,
+            "referral rewards",
+        )
+        .then(ext_self::resolve_claim_referral_rewards(
+            referrer_id,
+            ft_token_id,
+            U128(amount),
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_RESOLVE_TRANSFER,
+        ))
+    }
+
+    /// returns the amount claimed, a failed transfer goes back on the referrer's balance
+    #[private]
+    pub fn resolve_claim_referral_rewards(
+        &mut self,
+        referrer_id: AccountId,
+        ft_token_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        if promise_result_as_success().is_some() {
+            return amount;
+        }
+        let key = referral_key(&referrer_id, &ft_token_id);
+        let balance = self.referral_rewards.get(&key).unwrap_or(0);
+        self.referral_rewards.insert(&key, &(balance + amount.0));
+        U128(0)
+    }
+
+    /// views
+
+    pub fn get_referral_fee_rate(&self) -> u32 {
+        self.referral_fee_rate
+    }
+
+    pub fn get_referral_rewards(&self, account_id: AccountId, ft_token_id: AccountId) -> U128 {
+        U128(
+            self.referral_rewards
+                .get(&referral_key(&account_id, &ft_token_id))
+                .unwrap_or(0),
+        )
+    }
+}
+
+impl Contract {
+    /// books the fee of one lender position, the referrers' shares first and the rest
+    /// as protocol fee
+    pub(crate) fn internal_collect_fee(
+        &mut self,
+        sale: &Sale,
+        offer_referrer_id: Option<AccountId>,
+        ft_token_id: &AccountId,
+        fee: Balance,
+    ) {
+        let share = fee * self.referral_fee_rate as u128 / 10000;
+        let mut protocol_fee = fee;
+        for referrer_id in sale.referrer_id.iter().chain(offer_referrer_id.iter()) {
+            if share == 0 {
+                break;
+            }
+            let key = referral_key(referrer_id, ft_token_id);
+            let balance = self.referral_rewards.get(&key).unwrap_or(0);
+            self.referral_rewards.insert(&key, &(balance + share));
+            protocol_fee -= share;
+        }
+        self.internal_add_protocol_fee(ft_token_id, protocol_fee);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::*;
+
+/// credit history per account, borrower and lender side, kept as loans start and settle
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Reputation {
+    pub loans_taken: u64,
+    pub repaid_on_time: u64,
+    /// paid back after loan_duration, within the grace period
+    pub repaid_late: u64,
+    pub liquidated: u64,
+    pub total_borrowed: HashMap<FungibleTokenId, U128>,
+    pub total_lent: HashMap<FungibleTokenId, U128>,
+}
+
+impl Reputation {
+    /// loans paid back on time out of finished loans, in basis points, None without history
+    pub fn on_time_rate(&self) -> Option<u32> {
+        let finished = self.repaid_on_time + self.repaid_late + self.liquidated;
+        if finished == 0 {
+            None
+        } else {
+            Some((self.repaid_on_time * 10000 / finished) as u32)
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// views
+
+    pub fn get_reputation(&self, account_id: AccountId) -> Reputation {
+        self.reputations.get(&account_id).unwrap_or_default()
+    }
+
+    pub fn get_on_time_rate(&self, account_id: AccountId) -> Option<u32> {
+        self.get_reputation(account_id).on_time_rate()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_update_reputation<F: FnOnce(&mut Reputation)>(
+        &mut self,
+        account_id: &AccountId,
+        update: F,
+    ) {
+        let mut reputation = self.reputations.get(account_id).unwrap_or_default();
+        update(&mut reputation);
+        self.reputations.insert(account_id, &reputation);
+    }
+
+    /// a lender's min_on_time_rate, checked when the borrower accepts its offer. A borrower
+    /// with no finished loan passes, it had no chance to build a rate yet
+    pub(crate) fn assert_on_time_rate(&self, borrower_id: &AccountId, offer: &Offer) {
+        if offer.min_on_time_rate == 0 {
+            return;
+        }
+        let on_time_rate = match self
+            .reputations
+            .get(borrower_id)
+            .unwrap_or_default()
+            .on_time_rate()
+        {
+            Some(on_time_rate) => on_time_rate,
+            None => return,
+        };
+        assert!(
+            on_time_rate >= offer.min_on_time_rate,
+            "Offer {} requires an on-time rate of {}, borrower has {}",
+            offer.offer_id,
+            offer.min_on_time_rate,
+            on_time_rate
+        );
+    }
+
+    /// a loan of `sale` started, called once the NFT is in escrow
+    pub(crate) fn internal_record_loan_start(&mut self, sale: &Sale, offers: &[Offer]) {
+        let currency = sale.loan_currency.clone();
+        self.internal_update_reputation(&sale.owner_id, |reputation| {
+            reputation.loans_taken += 1;
+            reputation
+                .total_borrowed
+                .entry(currency.clone())
+                .or_insert(U128(0))
+                .0 += sale.loan_principal_amount;
+        });
+        for offer in offers {
+            if offer.status == LoanStatus::Processing as u32 {
+                self.internal_update_reputation(&offer.lender_id, |reputation| {
+                    reputation
+                        .total_lent
+                        .entry(currency.clone())
+                        .or_insert(U128(0))
+                        .0 += offer.loan_principal_amount;
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::*;
+use near_sdk::promise_result_as_success;
+use pawn_interest::{mul_div, Rounding};
+
+/// liquidity mining: a repaid loan earns NFTPAWN for its borrower and lenders in proportion to
+/// principal × seconds it ran. The pool is funded from the staking allocation with
+/// ft_transfer_call on reward_token_id, msg {"action": "fund_rewards"}, and accruals stop
+/// once it is spent
+
+/// reward_rates are reward units per REWARD_RATE_SCALE principal units per second
+pub const REWARD_RATE_SCALE: u128 = 1_000_000_000_000_000_000;
+
+/// rate of a collection in a currency, falls back to the rate of the currency
+fn reward_rate_key(ft_token_id: &str, nft_contract_id: Option<&str>) -> String {
+    match nft_contract_id {
+        Some(nft_contract_id) => format!("{}{}{}", nft_contract_id, DELIMETER, ft_token_id),
+        None => ft_token_id.to_string(),
+    }
+}
+
+/// principal × seconds × rate / REWARD_RATE_SCALE, saturates instead of overflowing, the
+/// reward pool caps what is accrued anyway
+pub(crate) fn reward_amount(principal: u128, seconds: u64, rate: u128) -> u128 {
+    match principal.checked_mul(seconds as u128) {
+        Some(weight) => mul_div(weight, rate, REWARD_RATE_SCALE, Rounding::Down),
+        None => mul_div(principal, rate, REWARD_RATE_SCALE, Rounding::Down)
+            .map(|per_second| per_second.saturating_mul(seconds as u128)),
+    }
+    .unwrap_or(u128::MAX)
+}
+
+#[near_bindgen]
+impl Contract {
+    /// only owner
+    pub fn set_reward_token_id(&mut self, reward_token_id: Option<ValidAccountId>) {
+        self.assert_owner();
+        self.reward_token_id = reward_token_id.map(|a| a.into());
+    }
+
+    /// only owner, `nft_contract_id` sets a collection's own rate
+    pub fn set_reward_rate(
+        &mut self,
+        ft_token_id: AccountId,
+        nft_contract_id: Option<AccountId>,
+        rate: U128,
+    ) {
+        self.assert_owner();
+        self.reward_rates.insert(
+            &reward_rate_key(&ft_token_id, nft_contract_id.as_deref()),
+            &rate.0,
+        );
+    }
+
+    #[payable]
+    pub fn claim_rewards(&mut self) -> Promise {
+        assert_one_yocto();
+        let reward_token_id = self.reward_token_id.clone().expect("Rewards are disabled");
+        let account_id = env::predecessor_account_id();
+        let amount = self.rewards.remove(&account_id).unwrap_or(0);
+        assert!(amount > 0, "No rewards");
+        self.internal_send(
+            &reward_token_id,
+            account_id.clone(),
+            amount,
+            "nftpawn rewards",
+        )
+        .then(ext_self::resolve_claim_rewards(
+            account_id,
+            U128(amount),
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_RESOLVE_TRANSFER,
+        ))
+    }
+
+    /// returns the amount claimed, a failed transfer goes back on the account's rewards
+    #[private]
+    pub fn resolve_claim_rewards(&mut self, account_id: AccountId, amount: U128) -> U128 {
+        if promise_result_as_success().is_some() {
+            return amount;
+        }
+        let balance = self.rewards.get(&account_id).unwrap_or(0);
+        self.rewards.insert(&account_id, &(balance + amount.0));
+        U128(0)
+    }
+
+    /// views
+
+    pub fn get_reward_token_id(&self) -> Option<AccountId> {
+        self.reward_token_id.clone()
+    }
+
+    pub fn get_reward_rate(
+        &self,
+        ft_token_id: AccountId,
+        nft_contract_id: Option<AccountId>,
+    ) -> U128 {
+        U128(self.internal_reward_rate(&ft_token_id, nft_contract_id.as_deref()))
+    }
+
+    /// rewards not yet accrued to anyone
+    pub fn get_reward_pool(&self) -> U128 {
+        U128(self.reward_pool)
+    }
+
+    pub fn get_rewards(&self, account_id: AccountId) -> U128 {
+        U128(self.rewards.get(&account_id).unwrap_or(0))
+    }
+}
+
+impl Contract {
+    fn internal_reward_rate(&self, ft_token_id: &str, nft_contract_id: Option<&str>) -> u128 {
+        nft_contract_id
+            .and_then(|nft_contract_id| {
+                self.reward_rates
+                    .get(&reward_rate_key(ft_token_id, Some(nft_contract_id)))
+            })
+            .or_else(|| self.reward_rates.get(&reward_rate_key(ft_token_id, None)))
+            .unwrap_or(0)
+    }
+
+    /// ft_on_transfer "fund_rewards"
+    pub(crate) fn internal_fund_rewards(&mut self, ft_token_id: &AccountId, amount: Balance) {
+        assert!(
+            self.reward_token_id.as_ref() == Some(ft_token_id),
+            "ft_token is invalid"
+        );
+        self.reward_pool += amount;
+    }
+
+    /// accrues the rewards of a repaid loan to its borrower and each lender
+    pub(crate) fn internal_accrue_rewards(&mut self, sale: &Sale, payoffs: &[LenderPayoff]) {
+        if self.reward_token_id.is_none() {
+            return;
+        }
+        let rate = self.internal_reward_rate(&sale.loan_currency, Some(&sale.nft_contract_id));
+        let now = env::block_timestamp() / 1000000000;
+        // a callable loan has no term to cap the time it ran
+        let seconds = if sale.loan_config == LoanConfig::Callable as u32 {
+            now - sale.started_at.0
+        } else {
+            min(now - sale.started_at.0, sale.loan_duration as u64)
+        };
+        for payoff in payoffs {
+            let amount = reward_amount(payoff.payoff.principal, seconds, rate);
+            self.internal_accrue_reward(&payoff.lender_id, amount);
+        }
+        let amount = reward_amount(sale.loan_principal_amount, seconds, rate);
+        self.internal_accrue_reward(&sale.owner_id, amount);
+    }
+
+    fn internal_accrue_reward(&mut self, account_id: &AccountId, amount: Balance) {
+        let amount = min(amount, self.reward_pool);
+        if amount == 0 {
+            return;
+        }
+        self.reward_pool -= amount;
+        let balance = self.rewards.get(account_id).unwrap_or(0);
+        self.rewards.insert(account_id, &(balance + amount));
+    }
+}
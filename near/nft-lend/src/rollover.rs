@@ -0,0 +1,199 @@
+use crate::*;
+
+/// rollover: a lender marks its offer renewable, the borrower opts in with set_auto_renew.
+/// At maturity the borrower's deposit pays the interest and fee owed, the loan restarts on
+/// the same terms and the NFT never leaves escrow
+
+#[near_bindgen]
+impl Contract {
+    /// borrower consent to roll its loan over at maturity
+    pub fn set_auto_renew(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        auto_renew: bool,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert_borrower(&sale);
+        sale.auto_renew = auto_renew;
+        sale.updated_at = U64(env::block_timestamp() / 1000000000);
+        self.sales.insert(&contract_and_token_id, &sale);
+    }
+
+    /// lender allows or stops the renewal of its offer
+    pub fn set_offer_renewable(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        offer_id: u32,
+        renewable: bool,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut offer = self
+            .internal_get_offer(&contract_and_token_id, offer_id)
+            .expect("No offer");
+        assert_lender(&offer);
+        offer.renewable = renewable;
+        offer.updated_at = U64(env::block_timestamp() / 1000000000);
+        self.internal_update_offer(&contract_and_token_id, &offer);
+    }
+
+    /// restarts a loan between maturity and the end of the grace period, called by the
+    /// borrower or a keeper once the borrower opted in
+    pub fn rollover_loan(&mut self, nft_contract_id: AccountId, token_id: String) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert!(
+            sale.status == LoanStatus::Processing as u32,
+            "Loan is not in processing"
+        );
+        assert!(sale.auto_renew, "Borrower did not opt in to renewal");
+        assert!(
+            sale.loan_config != LoanConfig::Callable as u32,
+            "Callable loans have no maturity to roll over"
+        );
+        // the receipt holder, not the borrower, owns the way out of the loan
+        assert!(
+            self.internal_receipt_of(&sale).is_none(),
+            "Loans with an obligation receipt cannot be rolled over"
+        );
+        let now = env::block_timestamp() / 1000000000;
+        assert!(
+            now >= sale.started_at.0 + sale.loan_duration as u64,
+            "Loan is not mature yet"
+        );
+        assert!(!self.internal_is_overdue(&sale), "Loan is overdue");
+
+        let offers = self.internal_offers(&contract_and_token_id);
+        assert!(
+            offers
+                .iter()
+                .filter(|offer| offer.status == LoanStatus::Processing as u32)
+                .all(|offer| offer.renewable),
+            "Lender did not allow renewal"
+        );
+        // at maturity the payoff carries the full interest of the term
+        let rate_index = self.internal_accrue_rate_index(&sale);
+        let payoffs = sale_payoffs(
+            &sale,
+            &offers,
+            now,
+            self.internal_fee_bps(&sale.owner_id),
+            rate_index,
+        );
+        let owed: u128 = payoffs
+            .iter()
+            .map(|p| p.payoff.total - p.payoff.principal)
+            .sum();
+        self.internal_withdraw_deposit(&sale.owner_id, &sale.loan_currency, owed);
+        let fees: u128 = payoffs.iter().map(|p| p.payoff.fee).sum();
+        self.internal_update_stats(&sale, |stats| {
+            stats.interest_paid.0 += owed - fees;
+            stats.fees_collected.0 += fees;
+        });
+        // rewards of the term that ends, before started_at moves to the new one
+        self.internal_accrue_rewards(&sale, &payoffs);
+
+        let ended_at = sale.started_at.0;
+        sale.started_at = U64(now);
+        sale.updated_at = U64(now);
+        self.sales.insert(&contract_and_token_id, &sale);
+        for mut offer in offers {
+            if offer.status == LoanStatus::Processing as u32 {
+                offer.started_at = U64(now);
+                offer.updated_at = U64(now);
+                self.internal_update_offer(&contract_and_token_id, &offer);
+            }
+        }
+        for LenderPayoff {
+            offer_id,
+            lender_id,
+            referrer_id,
+            payoff,
+        } in payoffs
+        {
+            let loan_currency = sale.loan_currency.clone();
+            self.internal_collect_fee(&sale, referrer_id, &loan_currency, payoff.fee);
+            self.internal_roll_position(
+                &sale,
+                offer_id,
+                ended_at,
+                lender_id,
+                payoff.total - payoff.principal - payoff.fee,
+            );
+        }
+        env::log(format!("Loan {} rolled over", contract_and_token_id).as_bytes());
+    }
+
+    /// pays the interest of a rolled over position to the holder of its note and swaps the
+    /// note for one of the new term
+    #[private]
+    pub fn resolve_note_rollover(
+        &mut self,
+        note_contract_id: AccountId,
+        note_token_id: TokenId,
+        nft_contract_id: AccountId,
+        token_id: String,
+        offer_id: u32,
+        amount: U128,
+    ) -> Promise {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let offer = self
+            .internal_get_offer(&contract_and_token_id, offer_id)
+            .expect("No offer");
+        let holder_id = match token_owner() {
+            Some(holder_id) => {
+                self.internal_burn_note(&note_contract_id, note_token_id);
+                holder_id
+            }
+            None => offer.lender_id.clone(),
+        };
+        self.internal_mint_note(&sale, &offer, holder_id.clone());
+        self.internal_send(
+            &sale.loan_currency,
+            holder_id,
+            amount.0,
+            "rollover interest",
+        )
+    }
+}
+
+impl Contract {
+    /// interest of a position whose term ended at `ended_at`, to the note holder if the loan
+    /// has notes
+    fn internal_roll_position(
+        &mut self,
+        sale: &Sale,
+        offer_id: u32,
+        ended_at: u64,
+        lender_id: AccountId,
+        amount: Balance,
+    ) -> Promise {
+        match &sale.note_contract_id {
+            None => self.internal_send(&sale.loan_currency, lender_id, amount, "rollover interest"),
+            Some(note_contract_id) => {
+                let note_token_id =
+                    note_token_id(&sale.nft_contract_id, &sale.token_id, ended_at, offer_id);
+                ext_contract::nft_token(
+                    note_token_id.clone(),
+                    note_contract_id,
+                    NO_DEPOSIT,
+                    GAS_FOR_NOTE,
+                )
+                .then(ext_self::resolve_note_rollover(
+                    note_contract_id.clone(),
+                    note_token_id,
+                    sale.nft_contract_id.clone(),
+                    sale.token_id.clone(),
+                    offer_id,
+                    U128(amount),
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_RESOLVE_NOTE,
+                ))
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::*;
 use near_sdk::promise_result_as_success;
+use pawn_interest::InterestConfig;
 
 //status : //0 : pending, 1: processing , 2 : done, 3: liquidated, 4: refunded
 pub enum LoanStatus {
@@ -12,7 +13,18 @@ pub enum LoanStatus {
     Canceled = 5,
 }
 
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+/// Sale.loan_config
+pub enum LoanConfig {
+    /// one lender funds the whole loan
+    Single = 0,
+    /// several offers fill loan_principal_amount together, each one is a tranche
+    Tranched = 1,
+    /// no maturity, runs until the lender calls it, loan_duration is the notice the
+    /// borrower then has to pay back, see callable.rs
+    Callable = 2,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Offer {
     pub offer_id: u32,
@@ -25,6 +37,21 @@ pub struct Offer {
     pub created_at: U64,
     pub updated_at: U64,
     pub started_at: U64,
+    /// lender agrees to roll the loan over at maturity, see rollover.rs
+    pub renewable: bool,
+    /// smallest on-time rate of the borrower this offer can be accepted by, basis points.
+    /// A borrower without a finished loan passes
+    pub min_on_time_rate: u32,
+    /// front-end credited a share of this position's fee, see referral.rs
+    pub referrer_id: Option<AccountId>,
+    /// accrues at the currency's variable rate, loan_interest_rate is not used, see
+    /// variable_rate.rs
+    pub variable_rate: bool,
+    /// rate index the position accrues from while lent out
+    pub rate_index: U128,
+    /// only borrower that can accept the offer, kept out of views, see counterparty.rs
+    #[serde(skip)]
+    pub allowed_borrower: Option<AccountId>,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
@@ -39,12 +66,31 @@ pub struct Sale {
     pub loan_currency: TokenId,
     pub loan_interest_rate: u32,
     pub loan_config: u32,
+    /// borrower asked for an obligation receipt, its holder gets the NFT back on pay back
+    pub obligation_receipt: bool,
+    /// where the notes and receipt of the running loan were minted, None if notes were off
+    /// when it started
+    pub note_contract_id: Option<AccountId>,
+    /// listed with nft_transfer_call, the NFT is already held by this contract
+    pub escrowed: bool,
+    /// last time the approval was seen valid, at listing or by verify_listing
+    pub verified_at: U64,
+    /// borrower agrees to roll the loan over at maturity, see rollover.rs
+    pub auto_renew: bool,
+    /// borrower lets keepers pay the loan back from its deposit, see deposit.rs
+    pub auto_repay: bool,
+    /// when the lender called a callable loan, 0 while it was not
+    pub called_at: U64,
+    /// front-end credited a share of the borrower side's fee, see referral.rs
+    pub referrer_id: Option<AccountId>,
+    /// only lenders that can fund the sale, empty for anyone, kept out of views
+    #[serde(skip)]
+    pub allowed_lenders: Vec<AccountId>,
     pub available_at: u64,
     pub status: u32,
     pub lender: AccountId,
     pub created_at: U64,
     pub updated_at: U64,
-    pub offers: Offers,
     pub started_at: U64,
 }
 
@@ -58,6 +104,20 @@ pub struct PurchaseArgs {
     pub loan_duration: u32,
     pub loan_interest_rate: u32,
     pub available_at: u64,
+    /// "counter_negotiation" and "accept_negotiation" only
+    pub negotiation_id: Option<U64>,
+    /// "counter_negotiation" only, seconds the counter stays valid
+    pub expires_in: Option<U64>,
+    /// "offer" and "offer_now", the lender allows rollover
+    pub renewable: Option<bool>,
+    /// "offer", see Offer.min_on_time_rate
+    pub min_on_time_rate: Option<u32>,
+    /// "offer" and "offer_now", see Offer.referrer_id
+    pub referrer_id: Option<AccountId>,
+    /// "offer", see Offer.variable_rate
+    pub variable_rate: Option<bool>,
+    /// "offer" and "offer_now", see Offer.allowed_borrower
+    pub allowed_borrower: Option<AccountId>,
 }
 
 #[near_bindgen]
@@ -72,6 +132,12 @@ impl Contract {
             loan_duration,
             loan_interest_rate,
             available_at,
+            renewable,
+            min_on_time_rate,
+            referrer_id,
+            variable_rate,
+            allowed_borrower,
+            ..
         } = near_sdk::serde_json::from_str(&msg).expect("Invalid PurchaseArgs");
 
         let sender_id = env::predecessor_account_id();
@@ -91,6 +157,13 @@ impl Contract {
 
         if action == "offer_now" {
             assert_ne!(sale.owner_id, sender_id, "Cannot buy your own sale.");
+            assert_sale_available(&sale);
+            assert_allowed_lender(&sale, &sender_id);
+            assert_referrers(&sale, &sender_id, referrer_id.as_ref());
+            assert!(
+                sale.loan_config != LoanConfig::Tranched as u32,
+                "Tranched sale must be filled by offers"
+            );
             let log_message = format!(
                 "Principle amount {}, real amount {}",
                 sale.loan_principal_amount, amount
@@ -105,7 +178,7 @@ impl Contract {
             sale.updated_at = U64(env::block_timestamp() / 1000000000);
             sale.started_at = U64(env::block_timestamp() / 1000000000);
             let new_offer = Offer {
-                offer_id: sale.offers.len() as u32 + 1,
+                offer_id: 0,
                 lender_id: sale.lender.clone(),
                 loan_principal_amount: sale.loan_principal_amount,
                 loan_duration: sale.loan_duration,
@@ -115,27 +188,41 @@ impl Contract {
                 started_at: U64(env::block_timestamp() / 1000000000),
                 status: LoanStatus::Processing as u32,
                 available_at: available_at,
+                renewable: renewable.unwrap_or(false),
+                min_on_time_rate: min_on_time_rate.unwrap_or(0),
+                referrer_id: referrer_id,
+                variable_rate: false,
+                rate_index: U128(0),
+                allowed_borrower: allowed_borrower,
             };
-            sale.offers.push(new_offer);
+            assert_allowed_borrower(&new_offer, &sale.owner_id);
+            self.internal_add_offer(&contract_and_token_id, new_offer);
             self.sales.insert(&contract_and_token_id, &sale);
-            ext_contract::nft_transfer(
-                env::current_account_id(),
+            self.process_purchase(
+                nft_contract_id.into(),
                 token_id,
+                sale.loan_currency,
+                U128(amount),
                 sale.approval_id,
-                "offer now".to_string(),
-                &nft_contract_id,
-                1,
-                GAS_FOR_NFT_TRANSFER,
-            )
-            .then(Promise::new(sale.owner_id.clone()).transfer(amount));
+                sale.owner_id,
+            );
             //
         } else if action == "offer" {
             assert_ne!(sale.owner_id, sender_id, "Cannot buy your own sale.");
-            sale.lender = sender_id;
+            assert!(
+                sale.status == LoanStatus::Open as u32,
+                "invalid loan status",
+            );
+            assert_allowed_lender(&sale, &sender_id);
+            assert_referrers(&sale, &sender_id, referrer_id.as_ref());
+            self.assert_offer_terms(&sale, amount, loan_duration);
+            if variable_rate == Some(true) {
+                self.assert_variable_offer(&sale);
+            }
             //insert offer
             let new_offer = Offer {
-                offer_id: sale.offers.len() as u32 + 1,
-                lender_id: sale.lender.clone(),
+                offer_id: 0,
+                lender_id: sender_id,
                 loan_principal_amount: amount,
                 loan_duration: loan_duration,
                 loan_interest_rate: loan_interest_rate,
@@ -144,18 +231,22 @@ impl Contract {
                 started_at: U64(0),
                 status: LoanStatus::Open as u32,
                 available_at: available_at,
+                renewable: renewable.unwrap_or(false),
+                min_on_time_rate: min_on_time_rate.unwrap_or(0),
+                referrer_id: referrer_id,
+                variable_rate: variable_rate.unwrap_or(false),
+                rate_index: U128(0),
+                allowed_borrower: allowed_borrower,
             };
-            sale.offers.push(new_offer);
-            //
-            self.sales.insert(&contract_and_token_id, &sale);
-            //
+            assert_allowed_borrower(&new_offer, &sale.owner_id);
+            self.internal_add_offer(&contract_and_token_id, new_offer);
         }
     }
 
     #[payable]
     pub fn pay_back_loan_by_near(&mut self, nft_contract_id: ValidAccountId, token_id: String) {
         let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
-        let mut sale = self
+        let sale = self
             .sales
             .get(&contract_and_token_id)
             .expect("No sale in ft_on_transfer");
@@ -165,72 +256,16 @@ impl Contract {
             amount > sale.loan_principal_amount,
             "Amount must greater than loan principal amount ",
         );
-        sale.status = LoanStatus::Done as u32;
-        sale.updated_at = U64(env::block_timestamp() / 1000000000);
-        self.sales.insert(&contract_and_token_id, &sale);
-        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id.clone());
-        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
-        let now: u128 = (env::block_timestamp() / 1000000000) as u128;
-        //expired + 2 days
-        let expired = sale.started_at.0 as u128 + sale.loan_duration as u128 + 2 * 86400;
-        assert!(expired > now, "invalid time to pay back loan ");
-        let real_pay_amount = self.calculate_pay_amount(
-            sale.loan_principal_amount,
-            sale.loan_duration,
-            sale.loan_interest_rate,
-            sale.started_at.0 as u128,
-            (env::block_timestamp() / 1000000000) as u128,
-        );
-        assert!(
-            real_pay_amount == amount,
-            "invalid payment amount, pay_amount:{}, input_amount{}",
-            real_pay_amount,
-            amount
+        let refund = self.process_payback_loan(
+            nft_contract_id.into(),
+            token_id,
+            "near".to_string(),
+            U128(amount),
+            sale.owner_id,
         );
-        let mut offers: Vec<Offer> = Vec::new();
-        for v in sale.offers {
-            if v.status == LoanStatus::Processing as u32 {
-                let mut clone = self.clone_offer(v);
-                clone.status = LoanStatus::Done as u32;
-                clone.updated_at = U64(env::block_timestamp() / 1000000000);
-                offers.push(clone);
-            } else {
-                let clone = self.clone_offer(v);
-                offers.push(clone);
-            }
+        if refund.0 > 0 {
+            Promise::new(user_account_id()).transfer(refund.0);
         }
-        self.sales.insert(
-            &contract_and_token_id,
-            &Sale {
-                owner_id: sale.owner_id.clone().into(),
-                approval_id: sale.approval_id,
-                nft_contract_id: sale.nft_contract_id.clone(),
-                token_id: sale.token_id.clone(),
-                loan_principal_amount: sale.loan_principal_amount,
-                loan_duration: sale.loan_duration,
-                loan_currency: sale.loan_currency,
-                loan_interest_rate: sale.loan_interest_rate,
-                loan_config: sale.loan_config,
-                available_at: sale.available_at,
-                created_at: sale.created_at,
-                updated_at: U64(env::block_timestamp() / 1000000000),
-                started_at: sale.started_at,
-                status: sale.status,
-                lender: sale.lender.clone(),
-                offers: offers,
-            },
-        );
-        Promise::new(sale.lender)
-            .transfer(amount)
-            .then(ext_contract::nft_transfer(
-                sale.owner_id,
-                token_id,
-                0,
-                "payout from market".to_string(),
-                &nft_contract_id,
-                1,
-                GAS_FOR_NFT_TRANSFER,
-            ));
     }
 
     /// for add sale see: nft_callbacks.rs
@@ -238,273 +273,219 @@ impl Contract {
     pub fn cancel_loan(&mut self, nft_contract_id: ValidAccountId, token_id: String) {
         let contract_id: AccountId = nft_contract_id.into();
         let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
-        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert_borrower(&sale);
         assert!(
-            sale.owner_id == env::predecessor_account_id(),
-            "invalid owner owner's loan:{}, signer:{}",
-            sale.owner_id,
-            env::predecessor_account_id(),
+            sale.status == LoanStatus::Open as u32,
+            "invalid loan status",
         );
+        self.internal_cancel_sale(&contract_and_token_id, sale);
+    }
+
+    /// anyone can check that an approval-based listing is still backed, a listing whose
+    /// approval was revoked or whose NFT moved is cancelled and its offers refunded
+    pub fn verify_listing(&mut self, nft_contract_id: AccountId, token_id: String) -> Promise {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
         assert!(
             sale.status == LoanStatus::Open as u32,
             "invalid loan status",
         );
-        if sale.status == LoanStatus::Open as u32 {
-            sale.status = LoanStatus::Canceled as u32;
-            let mut offers: Vec<Offer> = Vec::new();
-            for v in sale.offers {
-                if v.status == LoanStatus::Open as u32 {
-                    if sale.loan_currency == "near" {
-                        Promise::new(v.lender_id.clone()).transfer(U128(v.loan_principal_amount).0);
-                    } else {
-                        ext_contract::ft_transfer(
-                            v.lender_id.clone(),
-                            U128(v.loan_principal_amount),
-                            Some(String::from("refund from market")),
-                            &sale.loan_currency,
-                            1,
-                            GAS_FOR_FT_TRANSFER,
-                        );
-                    }
-                    let mut clone = self.clone_offer(v);
-                    clone.status = LoanStatus::Canceled as u32;
-                    clone.updated_at = U64(env::block_timestamp() / 1000000000);
-                    offers.push(clone);
-                }
+        assert!(!sale.escrowed, "Listing is held in escrow");
+        // enough for the refunds if the listing turns out to be stale
+        let open_offers = self
+            .internal_offers(&contract_and_token_id)
+            .iter()
+            .filter(|offer| offer.status == LoanStatus::Open as u32)
+            .count() as u64;
+        ext_contract::nft_is_approved(
+            token_id.clone(),
+            env::current_account_id(),
+            Some(sale.approval_id),
+            &nft_contract_id,
+            NO_DEPOSIT,
+            GAS_FOR_NFT_TOKEN,
+        )
+        .then(ext_self::resolve_verify_listing(
+            nft_contract_id,
+            token_id,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_RESOLVE_VERIFY + open_offers * GAS_PER_OFFER_REFUND,
+        ))
+    }
+
+    /// returns false if the listing was cancelled, only an nft_is_approved call that
+    /// returned false cancels it, a failed call leaves it as it is
+    #[private]
+    pub fn resolve_verify_listing(&mut self, nft_contract_id: AccountId, token_id: String) -> bool {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        if sale.status != LoanStatus::Open as u32 {
+            return true;
+        }
+        let approved = promise_result_as_success()
+            .and_then(|value| near_sdk::serde_json::from_slice::<bool>(&value).ok());
+        match approved {
+            Some(true) => {
+                sale.verified_at = U64(env::block_timestamp() / 1000000000);
+                self.sales.insert(&contract_and_token_id, &sale);
+                true
+            }
+            Some(false) => {
+                env::log(
+                    format!("Listing {} is no longer approved", contract_and_token_id).as_bytes(),
+                );
+                self.internal_cancel_sale(&contract_and_token_id, sale);
+                false
+            }
+            None => {
+                env::log(
+                    format!("Listing {} could not be verified", contract_and_token_id).as_bytes(),
+                );
+                true
             }
-            self.sales.insert(
-                &contract_and_token_id,
-                &Sale {
-                    owner_id: sale.owner_id.clone().into(),
-                    approval_id: sale.approval_id,
-                    nft_contract_id: sale.nft_contract_id.clone(),
-                    token_id: token_id.clone(),
-                    loan_principal_amount: sale.loan_principal_amount,
-                    loan_duration: sale.loan_duration,
-                    loan_currency: sale.loan_currency,
-                    loan_interest_rate: sale.loan_interest_rate,
-                    loan_config: sale.loan_config,
-                    available_at: sale.available_at,
-                    created_at: sale.created_at,
-                    updated_at: U64(env::block_timestamp() / 1000000000),
-                    started_at: sale.started_at,
-                    status: LoanStatus::Canceled as u32,
-                    lender: sale.lender.to_string(),
-                    offers: offers,
-                },
-            );
         }
     }
+
     #[payable]
     pub fn cancel_offer(&mut self, nft_contract_id: AccountId, token_id: String, offer_id: u32) {
-        let contract_id: AccountId = nft_contract_id.into();
-        let contract_and_token_id =
-            format!("{}{}{}", contract_id.clone(), DELIMETER, token_id.clone());
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
         let sale = self.sales.get(&contract_and_token_id).expect("No sale");
-        let mut offers: Vec<Offer> = Vec::new();
-        for v in sale.offers {
-            if v.offer_id == offer_id {
-                assert!(
-                    v.status == LoanStatus::Open as u32,
-                    "Unable cancel 'not open' offer "
-                );
-                if sale.loan_currency == "near" {
-                    Promise::new(v.lender_id.clone()).transfer(U128(v.loan_principal_amount).0);
-                } else {
-                    ext_contract::ft_transfer(
-                        v.lender_id.clone(),
-                        U128(v.loan_principal_amount),
-                        Some(String::from("refund from market")),
-                        &sale.loan_currency,
-                        1,
-                        GAS_FOR_FT_TRANSFER,
-                    );
-                }
-                let mut clone = self.clone_offer(v);
-                clone.status = LoanStatus::Canceled as u32;
-                clone.updated_at = U64(env::block_timestamp() / 1000000000);
-                offers.push(clone);
-            } else {
-                let clone = self.clone_offer(v);
-                offers.push(clone);
-            }
-        }
-        self.sales.insert(
-            &contract_and_token_id,
-            &Sale {
-                owner_id: sale.owner_id.clone().into(),
-                approval_id: sale.approval_id,
-                nft_contract_id: sale.nft_contract_id.clone(),
-                token_id: token_id.clone(),
-                loan_principal_amount: sale.loan_principal_amount,
-                loan_duration: sale.loan_duration,
-                loan_currency: sale.loan_currency,
-                loan_interest_rate: sale.loan_interest_rate,
-                loan_config: sale.loan_config,
-                available_at: sale.available_at,
-                created_at: sale.created_at,
-                updated_at: U64(env::block_timestamp() / 1000000000),
-                started_at: sale.started_at,
-                status: sale.status,
-                lender: sale.lender.to_string(),
-                offers: offers,
-            },
+        let offer = self
+            .internal_get_offer(&contract_and_token_id, offer_id)
+            .expect("No offer");
+        assert_lender(&offer);
+        assert!(
+            offer.status == LoanStatus::Open as u32,
+            "Unable cancel 'not open' offer "
         );
+        self.internal_cancel_offer(&contract_and_token_id, &sale, offer);
     }
 
     #[payable]
     pub fn liquidate_overdue_loan(&mut self, nft_contract_id: ValidAccountId, token_id: String) {
         let contract_id: AccountId = nft_contract_id.into();
         let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
-        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
-        let now: u128 = (env::block_timestamp() / 1000000000) as u128;
-        //expired + 2 days
-        let expired = sale.started_at.0 as u128 + sale.loan_duration as u128 + 2 * 86400;
-        assert!(expired < now, "invalid time to liquidate loan");
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert!(
+            self.internal_is_overdue(&sale),
+            "invalid time to liquidate loan"
+        );
         if sale.status == LoanStatus::Processing as u32 {
-            sale.status = LoanStatus::Liquidated as u32;
-            sale.updated_at = U64(env::block_timestamp() / 1000000000);
-            self.process_liquidate_loan(contract_id, token_id, sale.lender.clone());
-            let mut offers: Vec<Offer> = Vec::new();
-            for v in sale.offers {
-                if v.status == LoanStatus::Processing as u32 {
-                    let mut clone = self.clone_offer(v);
-                    clone.status = LoanStatus::Liquidated as u32;
-                    clone.updated_at = U64(env::block_timestamp() / 1000000000);
-                    offers.push(clone);
-                } else {
-                    let clone = self.clone_offer(v);
-                    offers.push(clone);
+            self.internal_liquidate_loan(contract_and_token_id, sale);
+        }
+    }
+
+    /// keeper entry point, skips loans that are not overdue instead of panicking
+    pub fn liquidate_many(&mut self, nft_contract_tokens: Vec<ContractAndTokenId>) -> Vec<bool> {
+        let mut liquidated = vec![];
+        for contract_and_token_id in nft_contract_tokens {
+            if env::prepaid_gas() - env::used_gas()
+                < GAS_PER_LIQUIDATION + self.internal_bundle_gas(&contract_and_token_id)
+            {
+                liquidated.push(false);
+                continue;
+            }
+            let sale = match self.sales.get(&contract_and_token_id) {
+                Some(sale) => sale,
+                None => {
+                    liquidated.push(false);
+                    continue;
                 }
+            };
+            if sale.status != LoanStatus::Processing as u32 || !self.internal_is_overdue(&sale) {
+                liquidated.push(false);
+                continue;
             }
-            self.sales.insert(
-                &contract_and_token_id,
-                &Sale {
-                    owner_id: sale.owner_id.clone().into(),
-                    approval_id: sale.approval_id,
-                    nft_contract_id: sale.nft_contract_id.clone(),
-                    token_id: sale.token_id.clone(),
-                    loan_principal_amount: sale.loan_principal_amount,
-                    loan_duration: sale.loan_duration,
-                    loan_currency: sale.loan_currency,
-                    loan_interest_rate: sale.loan_interest_rate,
-                    loan_config: sale.loan_config,
-                    available_at: sale.available_at,
-                    created_at: sale.created_at,
-                    updated_at: U64(env::block_timestamp() / 1000000000),
-                    started_at: sale.started_at,
-                    status: sale.status,
-                    lender: sale.lender.clone(),
-                    offers: offers,
-                },
-            );
+            self.internal_liquidate_loan(contract_and_token_id, sale);
+            liquidated.push(true);
         }
+        liquidated
     }
 
-    #[payable]
-    pub fn accept_offer(&mut self, nft_contract_id: AccountId, token_id: String, offer_id: u32) {
-        let contract_id: AccountId = nft_contract_id.into();
-        let contract_and_token_id =
-            format!("{}{}{}", contract_id.clone(), DELIMETER, token_id.clone());
-        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
-        assert!(
-            sale.status == LoanStatus::Open as u32,
-            "Loan is in proccessing "
-        );
-        let loan_currency = sale.loan_currency.clone();
-        let mut offers: Vec<Offer> = Vec::new();
-        let mut updated_sale = Sale {
-            owner_id: sale.owner_id.clone().into(),
-            approval_id: sale.approval_id,
-            nft_contract_id: sale.nft_contract_id.clone(),
-            token_id: token_id.clone(),
-            loan_principal_amount: sale.loan_principal_amount,
-            loan_duration: sale.loan_duration,
-            loan_currency: sale.loan_currency,
-            loan_interest_rate: sale.loan_interest_rate,
-            loan_config: sale.loan_config,
-            available_at: sale.available_at,
-            created_at: sale.created_at,
-            started_at: U64(env::block_timestamp() / 1000000000),
-            updated_at: U64(env::block_timestamp() / 1000000000),
-            status: LoanStatus::Processing as u32,
-            lender: sale.lender.to_string(),
-            offers: Vec::new(),
-        };
-        for v in sale.offers {
-            let mut clone = self.clone_offer(v);
-            clone.updated_at = U64(env::block_timestamp() / 1000000000);
-            if clone.offer_id == offer_id && clone.status == LoanStatus::Open as u32 {
-                if loan_currency == "near" {
-                    ext_contract::nft_transfer(
-                        env::current_account_id(),
-                        token_id.clone(),
-                        sale.approval_id,
-                        "payout from market".to_string(),
-                        &contract_id,
-                        1,
-                        GAS_FOR_NFT_TRANSFER,
-                    )
-                    .then(
-                        Promise::new(sale.owner_id.clone()).transfer(clone.loan_principal_amount),
-                    );
-                } else {
-                    self.process_purchase(
-                        contract_id.clone(),
-                        token_id.clone(),
-                        updated_sale.loan_currency.clone(),
-                        U128(clone.loan_principal_amount),
-                        sale.approval_id,
-                        sale.owner_id.clone(),
-                        sale.lender.clone(),
-                    );
-                }
-                clone.status = LoanStatus::Processing as u32;
-                clone.started_at = U64(env::block_timestamp() / 1000000000);
-                updated_sale.loan_duration = clone.loan_duration;
-                updated_sale.loan_principal_amount = clone.loan_principal_amount;
-                updated_sale.loan_interest_rate = clone.loan_interest_rate;
-            } else {
-                if clone.status == LoanStatus::Open as u32 {
-                    if loan_currency == "near" {
-                        Promise::new(clone.lender_id.clone())
-                            .transfer(U128(clone.loan_principal_amount.clone()).0);
-                    } else {
-                        ext_contract::ft_transfer(
-                            clone.lender_id.clone(),
-                            U128(clone.loan_principal_amount.clone()),
-                            Some(String::from("refund from market")),
-                            &updated_sale.loan_currency,
-                            1,
-                            GAS_FOR_FT_TRANSFER,
-                        );
-                    }
-                    clone.status = LoanStatus::Canceled as u32;
-                }
+    fn internal_liquidate_loan(
+        &mut self,
+        contract_and_token_id: ContractAndTokenId,
+        mut sale: Sale,
+    ) {
+        let now = U64(env::block_timestamp() / 1000000000);
+        sale.status = LoanStatus::Liquidated as u32;
+        sale.updated_at = now;
+        let offers = self.internal_offers(&contract_and_token_id);
+        let lender_ids: Vec<AccountId> = offers
+            .iter()
+            .filter(|offer| offer.status == LoanStatus::Processing as u32)
+            .map(|offer| offer.lender_id.clone())
+            .collect();
+        for mut offer in offers {
+            if offer.status != LoanStatus::Processing as u32 {
+                continue;
+            }
+            // a tranched loan keeps the NFT in escrow as a claim of its tranche holders,
+            // see buyout_claim in tranche.rs
+            if sale.loan_config != LoanConfig::Tranched as u32 {
+                self.internal_deliver_collateral(&sale, offer.offer_id, offer.lender_id.clone());
+                self.internal_release_offer_storage(&offer.lender_id);
             }
-            offers.push(clone);
+            offer.status = LoanStatus::Liquidated as u32;
+            offer.updated_at = now;
+            self.internal_update_offer(&contract_and_token_id, &offer);
+        }
+        // the obligation receipt is worthless once the loan defaulted
+        if let (Some(receipt_token_id), Some(note_contract_id)) =
+            (self.internal_receipt_of(&sale), &sale.note_contract_id)
+        {
+            self.internal_burn_note(note_contract_id, receipt_token_id);
+        }
+
+        // keeper bounty, paid from the protocol fees of the loan currency
+        let keeper_id = user_account_id();
+        let fees = self.protocol_fees.get(&sale.loan_currency).unwrap_or(0);
+        let bounty = liquidation_bounty(
+            &sale,
+            &lender_ids,
+            &keeper_id,
+            self.liquidation_bounty_rate,
+            fees,
+        );
+        if bounty > 0 {
+            self.protocol_fees
+                .insert(&sale.loan_currency, &(fees - bounty));
+            self.internal_send(&sale.loan_currency, keeper_id, bounty, "liquidation bounty")
+                .then(ext_self::resolve_liquidation_bounty(
+                    sale.loan_currency.clone(),
+                    U128(bounty),
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_RESOLVE_TRANSFER,
+                ));
         }
-        updated_sale.offers = offers;
-        self.sales.insert(&contract_and_token_id, &updated_sale);
+        self.sales.insert(&contract_and_token_id, &sale);
+        self.internal_update_stats(&sale, |stats| {
+            stats.active_principal.0 = stats
+                .active_principal
+                .0
+                .saturating_sub(sale.loan_principal_amount);
+            stats.active = stats.active.saturating_sub(1);
+            stats.liquidated += 1;
+        });
+        self.internal_update_reputation(&sale.owner_id, |reputation| {
+            reputation.liquidated += 1;
+        });
     }
 
-    #[private]
-    pub fn clone_offer(&mut self, v: Offer) -> Offer {
-        let clone = Offer {
-            offer_id: v.offer_id,
-            lender_id: v.lender_id,
-            loan_principal_amount: v.loan_principal_amount,
-            loan_duration: v.loan_duration,
-            loan_interest_rate: v.loan_interest_rate,
-            created_at: v.created_at,
-            updated_at: v.updated_at,
-            started_at: v.started_at,
-            status: v.status,
-            available_at: v.available_at,
-        };
-        clone
+    #[payable]
+    pub fn accept_offer(&mut self, nft_contract_id: AccountId, token_id: String, offer_id: u32) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert_borrower(&sale);
+        self.internal_accept_offer(nft_contract_id, token_id, offer_id);
     }
 
+    /// moves the NFT into escrow and pays the principal to the borrower once nft_token
+    /// confirms the contract owns it, see resolve_purchase
+    #[private]
     pub fn process_purchase(
         &mut self,
         nft_contract_id: AccountId,
@@ -513,29 +494,70 @@ impl Contract {
         amount: U128,
         approval_id: u64,
         borrower_id: AccountId,
-        lender_id: AccountId,
     ) -> Promise {
-        // let sale = self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
-
-        ext_contract::nft_transfer(
-            env::current_account_id(),
-            token_id,
-            approval_id,
-            "payout from market".to_string(),
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let owner_check = ext_contract::nft_token(
+            token_id.clone(),
             &nft_contract_id,
-            1,
-            GAS_FOR_NFT_TRANSFER,
-        )
-        .then(ext_self::resolve_purchase(
+            NO_DEPOSIT,
+            GAS_FOR_NFT_TOKEN,
+        );
+        // a sale listed with nft_transfer_call is already in escrow
+        let mut escrow = if sale.escrowed {
+            owner_check
+        } else {
+            ext_contract::nft_transfer(
+                env::current_account_id(),
+                token_id.clone(),
+                approval_id,
+                "payout from market".to_string(),
+                &nft_contract_id,
+                1,
+                GAS_FOR_NFT_TRANSFER,
+            )
+            .then(owner_check)
+        };
+        // bundle items are moved and checked the same way, resolve_purchase gets one
+        // nft_token result per NFT
+        for item in self
+            .bundle_items
+            .get(&contract_and_token_id)
+            .unwrap_or_default()
+        {
+            escrow = escrow.and(
+                ext_contract::nft_transfer(
+                    env::current_account_id(),
+                    item.token_id.clone(),
+                    item.approval_id,
+                    "payout from market".to_string(),
+                    &item.nft_contract_id,
+                    1,
+                    GAS_FOR_NFT_TRANSFER,
+                )
+                .then(ext_contract::nft_token(
+                    item.token_id,
+                    &item.nft_contract_id,
+                    NO_DEPOSIT,
+                    GAS_FOR_NFT_TOKEN,
+                )),
+            );
+        }
+        escrow.then(ext_self::resolve_purchase(
+            nft_contract_id,
+            token_id,
             ft_token_id,
             borrower_id,
             amount,
+            sale.started_at,
             &env::current_account_id(),
             NO_DEPOSIT,
             GAS_FOR_ROYALTIES,
         ))
     }
 
+    /// settles a loan paid back with `amount`, returns the overpayment
+    #[private]
     pub fn process_payback_loan(
         &mut self,
         nft_contract_id: AccountId,
@@ -543,154 +565,281 @@ impl Contract {
         ft_token_id: AccountId,
         amount: U128,
         borrower_id: AccountId,
-        lender_id: AccountId,
-    ) -> Promise {
-        let contract_id: AccountId = nft_contract_id.clone();
-        let contract_and_token_id =
-            format!("{}{}{}", contract_id.clone(), DELIMETER, token_id.clone());
-        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
-        let now: u128 = (env::block_timestamp() / 1000000000) as u128;
-        //expired + 2 days
-        let expired = sale.started_at.0 as u128 + sale.loan_duration as u128 + 2 * 86400;
-        assert!(expired > now, "invalid time to pay back loan ");
-        let real_pay_amount = self.calculate_pay_amount(
-            sale.loan_principal_amount,
-            sale.loan_duration,
-            sale.loan_interest_rate,
-            sale.started_at.0 as u128,
-            (env::block_timestamp() / 1000000000) as u128,
+    ) -> U128 {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert!(
+            sale.status == LoanStatus::Processing as u32,
+            "Loan is not in processing"
         );
+        let now: u128 = (env::block_timestamp() / 1000000000) as u128;
+        //expired + 2 days, or the end of the notice of a called loan
+        if let Some(expired) = loan_deadline(&sale) {
+            assert!(expired as u128 > now, "invalid time to pay back loan ");
+        }
+        let offers = self.internal_offers(&contract_and_token_id);
+        // the borrower's fee tier at repayment time, see staking.rs
+        let fee_bps = self.internal_fee_bps(&sale.owner_id);
+        let rate_index = self.internal_accrue_rate_index(&sale);
+        let payoffs = sale_payoffs(&sale, &offers, now as u64, fee_bps, rate_index);
+        let real_pay_amount: u128 = payoffs.iter().map(|p| p.payoff.total).sum();
         assert!(
-            real_pay_amount == amount.0,
+            amount.0 >= real_pay_amount,
             "invalid payment amount, pay_amount:{}, input_amount{}",
             real_pay_amount,
             amount.0
         );
-        let mut offers: Vec<Offer> = Vec::new();
-        for v in sale.offers {
-            if v.status == LoanStatus::Processing as u32 {
-                let mut clone = self.clone_offer(v);
-                clone.status = LoanStatus::Done as u32;
-                clone.updated_at = U64(env::block_timestamp() / 1000000000);
-                offers.push(clone);
-            } else {
-                let clone = self.clone_offer(v);
-                offers.push(clone);
+        for mut offer in offers {
+            if offer.status == LoanStatus::Processing as u32 {
+                offer.status = LoanStatus::Done as u32;
+                offer.updated_at = U64(now as u64);
+                self.internal_update_offer(&contract_and_token_id, &offer);
+                self.internal_release_offer_storage(&offer.lender_id);
             }
         }
-        self.sales.insert(
-            &contract_and_token_id,
-            &Sale {
-                owner_id: sale.owner_id.clone().into(),
-                approval_id: sale.approval_id,
-                nft_contract_id: sale.nft_contract_id.clone(),
-                token_id: sale.token_id.clone(),
-                loan_principal_amount: sale.loan_principal_amount,
-                loan_duration: sale.loan_duration,
-                loan_currency: sale.loan_currency,
-                loan_interest_rate: sale.loan_interest_rate,
-                loan_config: sale.loan_config,
-                available_at: sale.available_at,
-                created_at: sale.created_at,
-                updated_at: U64(env::block_timestamp() / 1000000000),
-                started_at: sale.started_at,
-                status: sale.status,
-                lender: sale.lender.clone(),
-                offers: offers,
-            },
-        );
-        ext_contract::ft_transfer(
+        sale.status = LoanStatus::Done as u32;
+        sale.updated_at = U64(now as u64);
+        self.sales.insert(&contract_and_token_id, &sale);
+        let interest: u128 = payoffs
+            .iter()
+            .map(|p| p.payoff.interest + p.payoff.discounted_interest)
+            .sum();
+        let fees: u128 = payoffs.iter().map(|p| p.payoff.fee).sum();
+        let on_time = loan_maturity(&sale).map_or(true, |maturity| now <= maturity as u128);
+        self.internal_update_reputation(&sale.owner_id, |reputation| {
+            if on_time {
+                reputation.repaid_on_time += 1;
+            } else {
+                reputation.repaid_late += 1;
+            }
+        });
+        self.internal_update_stats(&sale, |stats| {
+            stats.active_principal.0 = stats
+                .active_principal
+                .0
+                .saturating_sub(sale.loan_principal_amount);
+            stats.interest_paid.0 += interest;
+            stats.fees_collected.0 += fees;
+            stats.active = stats.active.saturating_sub(1);
+            stats.repaid += 1;
+        });
+        self.internal_accrue_rewards(&sale, &payoffs);
+        // each lender gets its principal and interest, the fee stays with the protocol: it
+        // funds keeper bounties and referral shares, lenders price their return in
+        // loan_interest_rate
+        for LenderPayoff {
+            offer_id,
             lender_id,
-            amount,
-            Some("pay_back_loan".to_string()),
-            &ft_token_id,
-            1,
-            GAS_FOR_FT_TRANSFER,
-        )
-        .then(ext_contract::nft_transfer(
-            borrower_id,
-            token_id,
-            0,
-            "payout from market".to_string(),
-            &nft_contract_id,
-            1,
-            GAS_FOR_NFT_TRANSFER,
-        ))
+            referrer_id,
+            payoff,
+        } in payoffs
+        {
+            self.internal_collect_fee(&sale, referrer_id, &ft_token_id, payoff.fee);
+            self.internal_pay_position(
+                &sale,
+                offer_id,
+                lender_id,
+                ft_token_id.clone(),
+                payoff.total - payoff.fee,
+                "pay_back_loan",
+            );
+        }
+        // the obligation receipt holder, if any, gets the NFT instead of the borrower
+        self.internal_return_collateral(&sale, borrower_id);
+        // overpayment goes back to the sender
+        U128(amount.0 - real_pay_amount)
     }
 
-    pub fn calculate_fee(&mut self, loan_principal_amount: u128) -> u128 {
-        let fee = loan_principal_amount * 1 / 100;
-        fee
+    /// `borrower_id` gets the fee of its staking tier, the flat fee otherwise
+    pub fn calculate_fee(
+        &self,
+        loan_principal_amount: u128,
+        borrower_id: Option<AccountId>,
+    ) -> u128 {
+        let fee_bps = borrower_id
+            .map(|borrower_id| self.internal_fee_bps(&borrower_id))
+            .unwrap_or_else(default_fee_bps);
+        InterestConfig {
+            fee_bps,
+            ..InterestConfig::default()
+        }
+        .fee(loan_principal_amount)
+        .expect("fee overflow")
     }
     pub fn calculate_pay_amount(
-        &mut self,
+        &self,
         loan_principal_amount: u128,
         loan_duration: u32,
         interest_rate: u32,
         loan_started_at: u128,
         pay_at: u128,
+        borrower_id: Option<AccountId>,
     ) -> u128 {
-        const DAY_SECS: u128 = 86400;
-        //1%(principla) + 100% interest to pay_at + 50% interest for the rest
-        let mut max_loan_day: u128 = loan_duration as u128 / DAY_SECS;
-        if max_loan_day == 0 {
-            max_loan_day = 1;
-        }
-        let mut loan_day: u128 = max_loan_day;
-        if pay_at < loan_started_at + loan_duration as u128 && pay_at > loan_started_at {
-            loan_day = ((pay_at - loan_started_at) / DAY_SECS) + 1;
-        }
-        if loan_day >= max_loan_day {
-            loan_day = max_loan_day
-        }
-        //100% interest loan day
-        let mut full_interst =
-            ((loan_principal_amount * (interest_rate as u128) / 10000) * loan_day) / 365;
-        if max_loan_day > loan_day {
-            //50% interest remain day
-            full_interst = full_interst
-                + (((loan_principal_amount * (interest_rate as u128) / 10000)
-                    * (max_loan_day - loan_day))
-                    / 365)
-                    / 2;
-        }
-        //1% fee (base on principal amount)
-        let fee = self.calculate_fee(loan_principal_amount);
-        fee + full_interst + loan_principal_amount
+        // see pawn-interest for the rules (interest to pay_at + 50% for the rest + the fee of
+        // the borrower's tier, 1% without one)
+        let fee_bps = borrower_id
+            .map(|borrower_id| self.internal_fee_bps(&borrower_id))
+            .unwrap_or_else(default_fee_bps);
+        loan_payoff(
+            loan_principal_amount,
+            loan_duration,
+            interest_rate,
+            loan_started_at as u64,
+            pay_at as u64,
+            fee_bps,
+        )
+        .total
     }
 
+    /// transfers the NFT and its bundle items out of escrow
     pub fn process_liquidate_loan(
         &mut self,
         nft_contract_id: AccountId,
         token_id: String,
         lender_id: AccountId,
     ) -> Promise {
-        ext_contract::nft_transfer(
-            lender_id,
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut transfers = ext_contract::nft_transfer(
+            lender_id.clone(),
             token_id,
             0,
             "liquidate loan".to_string(),
             &nft_contract_id,
             1,
             GAS_FOR_NFT_TRANSFER,
-        )
+        );
+        for item in self
+            .bundle_items
+            .get(&contract_and_token_id)
+            .unwrap_or_default()
+        {
+            transfers = transfers.and(ext_contract::nft_transfer(
+                lender_id.clone(),
+                item.token_id,
+                0,
+                "liquidate loan".to_string(),
+                &item.nft_contract_id,
+                1,
+                GAS_FOR_NFT_TRANSFER,
+            ));
+        }
+        transfers
     }
 
-    // #[private]
+    /// releases the principal if the NFT is in escrow, otherwise rolls the loan back to Open,
+    /// the lenders' funds stay with their offers
+    #[private]
     pub fn resolve_purchase(
         &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
         ft_token_id: AccountId,
         borrower_id: AccountId,
         price: U128,
+        started_at: U64,
     ) -> U128 {
-        ext_contract::ft_transfer(
-            borrower_id,
-            price,
-            None,
-            &ft_token_id,
-            1,
-            GAS_FOR_FT_TRANSFER,
-        );
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        // only the purchase that left the sale processing settles it, once
+        if sale.status != LoanStatus::Processing as u32 || sale.started_at.0 != started_at.0 {
+            env::log(
+                format!(
+                    "Purchase of {} started at {} is no longer processing",
+                    contract_and_token_id, started_at.0
+                )
+                .as_bytes(),
+            );
+            return U128(0);
+        }
+        let offers = self.internal_offers(&contract_and_token_id);
+        let owners = token_owners();
+        let current_account_id = env::current_account_id();
+        if owners
+            .iter()
+            .all(|owner| owner.as_ref() == Some(&current_account_id))
+        {
+            // payouts follow the notes of this loan even if notes are turned off later
+            sale.note_contract_id = self.note_contract_id.clone();
+            self.sales.insert(&contract_and_token_id, &sale);
+            for offer in offers.iter() {
+                if offer.status == LoanStatus::Processing as u32 {
+                    self.internal_mint_note(&sale, offer, offer.lender_id.clone());
+                }
+            }
+            self.internal_mint_receipt(&sale);
+            self.internal_send(&ft_token_id, borrower_id, price.0, "payout from market");
+            // loans started by offer_now, accept_offer, accept_tranches or a negotiation
+            self.internal_record_loan_start(&sale, &offers);
+            self.internal_update_stats(&sale, |stats| {
+                stats.volume.0 += price.0;
+                stats.active_principal.0 += price.0;
+                stats.open = stats.open.saturating_sub(1);
+                stats.active += 1;
+            });
+        } else {
+            env::log(
+                format!(
+                    "NFT {} is not in escrow, loan is back to open",
+                    contract_and_token_id
+                )
+                .as_bytes(),
+            );
+            // an NFT that did arrive stays in escrow for the sale's own token, bundle items go
+            // back to the borrower and have to be approved again. An NFT whose lookup failed
+            // may have arrived, it is sent back: the transfer fails if it did not
+            let lookups_failed = failed_lookups();
+            let failed = |i: usize| lookups_failed.get(i).copied().unwrap_or(false);
+            let held = |i: usize| {
+                owners
+                    .get(i)
+                    .map_or(false, |owner| owner.as_ref() == Some(&current_account_id))
+            };
+            if held(0) {
+                sale.escrowed = true;
+            } else if failed(0) && !sale.escrowed {
+                ext_contract::nft_transfer(
+                    sale.owner_id.clone(),
+                    sale.token_id.clone(),
+                    0,
+                    "sale not in escrow".to_string(),
+                    &sale.nft_contract_id,
+                    1,
+                    GAS_FOR_NFT_TRANSFER,
+                );
+            }
+            let items = self
+                .bundle_items
+                .get(&contract_and_token_id)
+                .unwrap_or_default();
+            for (i, item) in items.iter().enumerate() {
+                if held(i + 1) || failed(i + 1) {
+                    ext_contract::nft_transfer(
+                        sale.owner_id.clone(),
+                        item.token_id.clone(),
+                        0,
+                        "bundle not in escrow".to_string(),
+                        &item.nft_contract_id,
+                        1,
+                        GAS_FOR_NFT_TRANSFER,
+                    );
+                }
+            }
+            self.internal_clear_bundle(&contract_and_token_id);
+            let now = U64(env::block_timestamp() / 1000000000);
+            for mut offer in offers {
+                if offer.status == LoanStatus::Processing as u32 {
+                    offer.status = LoanStatus::Open as u32;
+                    offer.started_at = U64(0);
+                    offer.updated_at = now;
+                    self.internal_update_offer(&contract_and_token_id, &offer);
+                }
+            }
+            sale.status = LoanStatus::Open as u32;
+            sale.lender = "".to_string();
+            sale.started_at = U64(0);
+            sale.updated_at = now;
+            self.sales.insert(&contract_and_token_id, &sale);
+        }
         U128(0)
     }
 
@@ -698,6 +847,17 @@ impl Contract {
     pub fn resolve_offer(&mut self) -> U128 {
         U128(0)
     }
+
+    /// returns the bounty paid, a failed transfer goes back to the protocol fees
+    #[private]
+    pub fn resolve_liquidation_bounty(&mut self, ft_token_id: AccountId, amount: U128) -> U128 {
+        if promise_result_as_success().is_some() {
+            return amount;
+        }
+        let fees = self.protocol_fees.get(&ft_token_id).unwrap_or(0);
+        self.protocol_fees.insert(&ft_token_id, &(fees + amount.0));
+        U128(0)
+    }
 }
 
 /// self call
@@ -706,8 +866,151 @@ impl Contract {
 trait ExtSelf {
     fn resolve_purchase(
         &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
         ft_token_id: AccountId,
         borrower_id: AccountId,
         price: U128,
+        started_at: U64,
     ) -> Promise;
+
+    fn resolve_verify_listing(&mut self, nft_contract_id: AccountId, token_id: String) -> bool;
+
+    fn resolve_claim_referral_rewards(
+        &mut self,
+        referrer_id: AccountId,
+        ft_token_id: AccountId,
+        amount: U128,
+    ) -> U128;
+
+    fn resolve_claim_rewards(&mut self, account_id: AccountId, amount: U128) -> U128;
+
+    fn resolve_withdraw_stake(&mut self, account_id: AccountId, amount: U128) -> U128;
+
+    fn resolve_withdraw_deposit(
+        &mut self,
+        account_id: AccountId,
+        ft_token_id: AccountId,
+        amount: U128,
+    ) -> U128;
+
+    fn resolve_call_loan(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        caller_id: AccountId,
+    );
+
+    fn resolve_liquidation_bounty(&mut self, ft_token_id: AccountId, amount: U128) -> U128;
+
+    fn resolve_note_payout(
+        &mut self,
+        note_contract_id: AccountId,
+        note_token_id: TokenId,
+        lender_id: AccountId,
+        ft_token_id: AccountId,
+        amount: U128,
+        memo: String,
+    ) -> Promise;
+
+    fn resolve_note_collateral(
+        &mut self,
+        note_contract_id: AccountId,
+        note_token_id: TokenId,
+        receiver_id: AccountId,
+        nft_contract_id: AccountId,
+        token_id: String,
+    ) -> Promise;
+
+    fn resolve_note_rollover(
+        &mut self,
+        note_contract_id: AccountId,
+        note_token_id: TokenId,
+        nft_contract_id: AccountId,
+        token_id: String,
+        offer_id: u32,
+        amount: U128,
+    ) -> Promise;
+}
+
+impl Contract {
+    /// refunds an open offer and gives its storage back
+    pub(crate) fn internal_cancel_offer(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        sale: &Sale,
+        mut offer: Offer,
+    ) {
+        self.internal_send(
+            &sale.loan_currency,
+            offer.lender_id.clone(),
+            offer.loan_principal_amount,
+            "refund from market",
+        );
+        offer.status = LoanStatus::Canceled as u32;
+        offer.updated_at = U64(env::block_timestamp() / 1000000000);
+        self.internal_update_offer(contract_and_token_id, &offer);
+        self.internal_release_offer_storage(&offer.lender_id);
+    }
+
+    /// starts the loan on `offer_id`, every other open offer is refunded
+    pub(crate) fn internal_accept_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        offer_id: u32,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert!(
+            sale.status == LoanStatus::Open as u32,
+            "Loan is in proccessing "
+        );
+        assert!(
+            sale.loan_config != LoanConfig::Tranched as u32,
+            "Tranched sale must be accepted with accept_tranches"
+        );
+        let now = U64(env::block_timestamp() / 1000000000);
+        let mut accepted = false;
+        for mut offer in self.internal_offers(&contract_and_token_id) {
+            if offer.status != LoanStatus::Open as u32 {
+                continue;
+            }
+            offer.updated_at = now;
+            if offer.offer_id == offer_id {
+                self.assert_on_time_rate(&sale.owner_id, &offer);
+                assert_allowed_borrower(&offer, &sale.owner_id);
+                offer.status = LoanStatus::Processing as u32;
+                offer.started_at = now;
+                sale.lender = offer.lender_id.clone();
+                sale.loan_duration = offer.loan_duration;
+                sale.loan_principal_amount = offer.loan_principal_amount;
+                sale.loan_interest_rate = offer.loan_interest_rate;
+                accepted = true;
+            } else {
+                self.internal_send(
+                    &sale.loan_currency,
+                    offer.lender_id.clone(),
+                    offer.loan_principal_amount,
+                    "refund from market",
+                );
+                offer.status = LoanStatus::Canceled as u32;
+                self.internal_release_offer_storage(&offer.lender_id);
+            }
+            self.internal_update_offer(&contract_and_token_id, &offer);
+        }
+        assert!(accepted, "Offer is not open");
+        sale.status = LoanStatus::Processing as u32;
+        sale.started_at = now;
+        sale.updated_at = now;
+        self.sales.insert(&contract_and_token_id, &sale);
+        self.process_purchase(
+            nft_contract_id,
+            token_id,
+            sale.loan_currency,
+            U128(sale.loan_principal_amount),
+            sale.approval_id,
+            sale.owner_id,
+        );
+    }
 }
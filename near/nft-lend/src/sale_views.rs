@@ -1,5 +1,25 @@
 use crate::*;
 
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PayoffQuote {
+    pub principal: U128,
+    /// full-rate interest up to the quoted time
+    pub interest: U128,
+    /// reduced interest for the rest of the loan duration
+    pub discounted_interest: U128,
+    pub fee: U128,
+    pub total: U128,
+    /// yearly rate in basis points the loan accrues now, the current variable rate for
+    /// variable-rate loans
+    pub interest_rate: u32,
+    /// the loan follows its collection's rate pool, the listed loan_interest_rate is unused
+    pub variable_rate: bool,
+    /// pay back must happen before this time (seconds), grace period included, 0 for a
+    /// callable loan that was not called
+    pub deadline: U64,
+}
+
 #[near_bindgen]
 impl Contract {
 
@@ -112,8 +132,128 @@ impl Contract {
         tmp
     }
 
+    /// processing loans past the grace period, `from_index`/`limit` page over all sales
+    pub fn get_overdue_loans(
+        &self,
+        from_index: U64,
+        limit: u64,
+    ) -> Vec<Sale> {
+        let mut tmp = vec![];
+        let sales = self.sales.values_as_vector();
+        let start = u64::from(from_index);
+        let end = min(start + limit, sales.len());
+        for i in start..end {
+            let sale = sales.get(i).unwrap();
+            if sale.status == LoanStatus::Processing as u32 && self.internal_is_overdue(&sale) {
+                tmp.push(sale);
+            }
+        }
+        tmp
+    }
+
+    /// open listings, `from_index`/`limit` page over all sales. With `verified_since`
+    /// (seconds) approval-based listings not verified since then are left out,
+    /// escrowed listings are always backed
+    pub fn get_open_sales(
+        &self,
+        from_index: U64,
+        limit: u64,
+        verified_since: Option<U64>,
+    ) -> Vec<Sale> {
+        let mut tmp = vec![];
+        let sales = self.sales.values_as_vector();
+        let start = u64::from(from_index);
+        let end = min(start + limit, sales.len());
+        for i in start..end {
+            let sale = sales.get(i).unwrap();
+            if sale.status != LoanStatus::Open as u32 {
+                continue;
+            }
+            if let Some(verified_since) = verified_since {
+                if !sale.escrowed && sale.verified_at.0 < verified_since.0 {
+                    continue;
+                }
+            }
+            tmp.push(sale);
+        }
+        tmp
+    }
+
+    /// amount pay_back_loan expects at `at_timestamp` (seconds, defaults to now)
+    pub fn get_payoff_quote(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        at_timestamp: Option<U64>,
+    ) -> PayoffQuote {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert!(
+            sale.status == LoanStatus::Processing as u32,
+            "Loan is not in processing"
+        );
+        let pay_at = at_timestamp
+            .map(|t| t.0)
+            .unwrap_or(env::block_timestamp() / 1000000000);
+        let offers = self.internal_offers(&contract_and_token_id);
+        let variable_rate = offers
+            .iter()
+            .any(|offer| offer.status == LoanStatus::Processing as u32 && offer.variable_rate);
+        let payoff = sale_payoff(
+            &sale,
+            &offers,
+            pay_at,
+            self.internal_fee_bps(&sale.owner_id),
+            self.internal_rate_index(&sale, pay_at),
+        );
+        PayoffQuote {
+            principal: U128(payoff.principal),
+            interest: U128(payoff.interest),
+            discounted_interest: U128(payoff.discounted_interest),
+            fee: U128(payoff.fee),
+            total: U128(payoff.total),
+            interest_rate: if variable_rate {
+                self.get_variable_rate(sale.nft_contract_id.clone(), sale.loan_currency.clone())
+            } else {
+                sale.loan_interest_rate
+            },
+            variable_rate,
+            deadline: U64(loan_deadline(&sale).unwrap_or(0)),
+        }
+    }
+
     pub fn get_sale(&self, nft_contract_token: ContractAndTokenId) -> Option<Sale> {
         self.sales.get(&nft_contract_token)
     }
+
+    pub fn get_supply_offers(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+    ) -> U64 {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        U64(self.offer_counts.get(&contract_and_token_id).unwrap_or(0) as u64)
+    }
+
+    /// offers on the current listing of a sale by offer_id, `from_index` starts at 0
+    pub fn get_offers(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        from_index: U64,
+        limit: u64,
+    ) -> Vec<Offer> {
+        let mut tmp = vec![];
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let count = self.offer_counts.get(&contract_and_token_id).unwrap_or(0) as u64;
+        let start = u64::from(from_index);
+        let end = min(start + limit, count);
+        for i in start..end {
+            if let Some(offer) = self.internal_get_offer(&contract_and_token_id, i as u32 + 1) {
+                tmp.push(offer);
+            }
+        }
+        tmp
+    }
     
 }
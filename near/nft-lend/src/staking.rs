@@ -0,0 +1,164 @@
+use crate::*;
+use near_sdk::promise_result_as_success;
+use pawn_interest::InterestConfig;
+
+/// NFTPAWN staking for fee discounts: borrowers lock reward_token_id in this contract with
+/// ft_transfer_call, msg {"action": "stake"}, and pay the fee of the best fee_tiers entry their
+/// stake reaches. Unstaked tokens stop counting at once and can be withdrawn after
+/// STAKE_COOLDOWN. A stake is paid back in the token it was made in, a stake in an earlier
+/// reward_token_id no longer counts for the fee
+
+/// seconds between unstake and withdraw_stake
+pub const STAKE_COOLDOWN: u64 = 7 * 86400;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Stake {
+    /// token staked, reward_token_id at the time
+    pub ft_token_id: AccountId,
+    /// counts for the fee tier
+    pub amount: u128,
+    /// waiting for the cooldown
+    pub unlocking: u128,
+    pub unlock_at: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeTier {
+    pub min_stake: U128,
+    /// protocol fee, share of the principal
+    pub fee_bps: u32,
+}
+
+/// the flat fee of borrowers without a tier
+pub(crate) fn default_fee_bps() -> u32 {
+    InterestConfig::default().fee_bps
+}
+
+#[near_bindgen]
+impl Contract {
+    /// only owner, replaces the fee schedule
+    pub fn set_fee_tiers(&mut self, fee_tiers: Vec<FeeTier>) {
+        self.assert_owner();
+        for tier in fee_tiers.iter() {
+            assert!(
+                tier.fee_bps <= default_fee_bps(),
+                "A tier cannot charge more than {} bps",
+                default_fee_bps()
+            );
+        }
+        self.fee_tiers = fee_tiers;
+    }
+
+    /// starts the cooldown of `amount` of the caller's stake
+    pub fn unstake(&mut self, amount: U128) {
+        let account_id = env::predecessor_account_id();
+        let mut stake = self.stakes.get(&account_id).unwrap_or_default();
+        assert!(
+            amount.0 <= stake.amount,
+            "Insufficient stake: {}, requested {}",
+            stake.amount,
+            amount.0
+        );
+        stake.amount -= amount.0;
+        stake.unlocking += amount.0;
+        stake.unlock_at = U64(env::block_timestamp() / 1000000000 + STAKE_COOLDOWN);
+        self.stakes.insert(&account_id, &stake);
+    }
+
+    #[payable]
+    pub fn withdraw_stake(&mut self) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut stake = self.stakes.get(&account_id).expect("No stake");
+        assert!(stake.unlocking > 0, "Nothing to withdraw");
+        assert!(
+            env::block_timestamp() / 1000000000 >= stake.unlock_at.0,
+            "Stake is locked until {}",
+            stake.unlock_at.0
+        );
+        let amount = stake.unlocking;
+        stake.unlocking = 0;
+        // kept until the transfer resolves, a failed one is paid back in the same token
+        self.stakes.insert(&account_id, &stake);
+        self.internal_send(&stake.ft_token_id, account_id.clone(), amount, "unstake")
+            .then(ext_self::resolve_withdraw_stake(
+                account_id,
+                U128(amount),
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+    }
+
+    /// returns the amount withdrawn, a failed transfer can be withdrawn again
+    #[private]
+    pub fn resolve_withdraw_stake(&mut self, account_id: AccountId, amount: U128) -> U128 {
+        let mut stake = self.stakes.get(&account_id).expect("No stake");
+        if promise_result_as_success().is_some() {
+            if stake.amount == 0 && stake.unlocking == 0 {
+                self.stakes.remove(&account_id);
+            }
+            return amount;
+        }
+        stake.unlocking += amount.0;
+        self.stakes.insert(&account_id, &stake);
+        U128(0)
+    }
+
+    /// views
+
+    pub fn get_fee_tiers(&self) -> Vec<FeeTier> {
+        self.fee_tiers.clone()
+    }
+
+    pub fn get_stake(&self, account_id: AccountId) -> Stake {
+        self.stakes.get(&account_id).unwrap_or_default()
+    }
+
+    /// fee in basis points `account_id` pays as a borrower
+    pub fn get_fee_bps(&self, account_id: AccountId) -> u32 {
+        self.internal_fee_bps(&account_id)
+    }
+}
+
+impl Contract {
+    /// ft_on_transfer "stake"
+    pub(crate) fn internal_stake(
+        &mut self,
+        account_id: &AccountId,
+        ft_token_id: &AccountId,
+        amount: Balance,
+    ) {
+        assert!(
+            self.reward_token_id.as_ref() == Some(ft_token_id),
+            "ft_token is invalid"
+        );
+        let mut stake = self.stakes.get(account_id).unwrap_or_else(|| Stake {
+            ft_token_id: ft_token_id.clone(),
+            ..Stake::default()
+        });
+        assert!(
+            &stake.ft_token_id == ft_token_id,
+            "Stake is held in {}, withdraw it first",
+            stake.ft_token_id
+        );
+        stake.amount += amount;
+        self.stakes.insert(account_id, &stake);
+    }
+
+    pub(crate) fn internal_fee_bps(&self, borrower_id: &AccountId) -> u32 {
+        let staked = self
+            .stakes
+            .get(borrower_id)
+            .filter(|stake| self.reward_token_id.as_ref() == Some(&stake.ft_token_id))
+            .map(|stake| stake.amount)
+            .unwrap_or(0);
+        self.fee_tiers
+            .iter()
+            .filter(|tier| staked >= tier.min_stake.0)
+            .map(|tier| tier.fee_bps)
+            .fold(default_fee_bps(), min)
+    }
+}
@@ -0,0 +1,127 @@
+use crate::*;
+
+/// running protocol statistics, kept per loan currency and per NFT contract and currency so
+/// dashboards do not have to scan every Sale
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LoanStats {
+    /// principal of every loan started
+    pub volume: U128,
+    /// principal of the loans still running
+    pub active_principal: U128,
+    /// interest paid to lenders, rollovers included
+    pub interest_paid: U128,
+    pub fees_collected: U128,
+    pub listed: u64,
+    /// listings waiting for a lender
+    pub open: u64,
+    /// loans running
+    pub active: u64,
+    pub repaid: u64,
+    pub liquidated: u64,
+    pub canceled: u64,
+    /// liquidated out of finished loans, in basis points
+    pub default_rate: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionStats {
+    pub nft_contract_id: AccountId,
+    pub loan_currency: FungibleTokenId,
+    pub stats: LoanStats,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// views
+
+    pub fn get_currency_stats(&self, ft_token_id: AccountId) -> LoanStats {
+        self.currency_stats.get(&ft_token_id).unwrap_or_default()
+    }
+
+    pub fn get_supply_collection_stats(&self) -> U64 {
+        U64(self.collection_stats.len())
+    }
+
+    /// stats of every NFT contract and loan currency pair seen so far
+    pub fn get_collection_stats(&self, from_index: U64, limit: u64) -> Vec<CollectionStats> {
+        let keys = self.collection_stats.keys_as_vector();
+        let values = self.collection_stats.values_as_vector();
+        let start = u64::from(from_index);
+        let end = min(start + limit, keys.len());
+        (start..end)
+            .map(|i| {
+                let key = keys.get(i).unwrap();
+                let mut parts = key.splitn(2, DELIMETER);
+                CollectionStats {
+                    nft_contract_id: parts.next().unwrap().to_string(),
+                    loan_currency: parts.next().unwrap_or_default().to_string(),
+                    stats: values.get(i).unwrap(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn get_stats_by_nft_contract_id(
+        &self,
+        nft_contract_id: AccountId,
+        ft_token_id: AccountId,
+    ) -> LoanStats {
+        self.collection_stats
+            .get(&format!("{}{}{}", nft_contract_id, DELIMETER, ft_token_id))
+            .unwrap_or_default()
+    }
+}
+
+impl Contract {
+    /// applies `update` to the stats of the sale's currency and of its NFT contract
+    pub(crate) fn internal_update_stats<F: Fn(&mut LoanStats)>(&mut self, sale: &Sale, update: F) {
+        let mut stats = self
+            .currency_stats
+            .get(&sale.loan_currency)
+            .unwrap_or_default();
+        update(&mut stats);
+        stats.update_default_rate();
+        self.currency_stats.insert(&sale.loan_currency, &stats);
+
+        let key = format!(
+            "{}{}{}",
+            sale.nft_contract_id, DELIMETER, sale.loan_currency
+        );
+        let mut stats = self.collection_stats.get(&key).unwrap_or_default();
+        update(&mut stats);
+        stats.update_default_rate();
+        self.collection_stats.insert(&key, &stats);
+    }
+}
+
+impl Default for LoanStats {
+    fn default() -> Self {
+        LoanStats {
+            volume: U128(0),
+            active_principal: U128(0),
+            interest_paid: U128(0),
+            fees_collected: U128(0),
+            listed: 0,
+            open: 0,
+            active: 0,
+            repaid: 0,
+            liquidated: 0,
+            canceled: 0,
+            default_rate: 0,
+        }
+    }
+}
+
+impl LoanStats {
+    fn update_default_rate(&mut self) {
+        let finished = self.repaid + self.liquidated;
+        self.default_rate = if finished == 0 {
+            0
+        } else {
+            (self.liquidated * 10000 / finished) as u32
+        };
+    }
+}
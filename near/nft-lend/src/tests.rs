@@ -0,0 +1,407 @@
+/* unit tests */
+#[cfg(test)]
+use crate::rewards::*;
+use crate::tranche::*;
+use crate::*;
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::{testing_env, MockedBlockchain};
+use pawn_interest::{mul_div, Rounding};
+
+const NEAR: u128 = 1_000_000_000_000_000_000_000_000;
+const DAY: u64 = 86400;
+
+fn account(index: usize) -> AccountId {
+    accounts(index).into()
+}
+
+fn get_context(predecessor: ValidAccountId) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder.predecessor_account_id(predecessor);
+    builder
+}
+
+/// a running 30-day loan of 100 NEAR at 10% from account 1 to account 0
+fn sample_sale(loan_config: LoanConfig) -> Sale {
+    Sale {
+        owner_id: account(0),
+        approval_id: 0,
+        nft_contract_id: "nft.near".to_string(),
+        token_id: "1".to_string(),
+        loan_principal_amount: 100 * NEAR,
+        loan_duration: 30 * DAY as u32,
+        loan_currency: "near".to_string(),
+        loan_interest_rate: 1000,
+        loan_config: loan_config as u32,
+        obligation_receipt: false,
+        note_contract_id: None,
+        escrowed: false,
+        verified_at: U64(0),
+        auto_renew: false,
+        auto_repay: false,
+        called_at: U64(0),
+        referrer_id: None,
+        allowed_lenders: vec![],
+        available_at: 0,
+        status: LoanStatus::Processing as u32,
+        lender: account(1),
+        created_at: U64(0),
+        updated_at: U64(0),
+        started_at: U64(0),
+    }
+}
+
+fn sample_offer(
+    offer_id: u32,
+    lender_index: usize,
+    loan_principal_amount: u128,
+    loan_interest_rate: u32,
+) -> Offer {
+    Offer {
+        offer_id,
+        lender_id: account(lender_index),
+        loan_principal_amount,
+        loan_duration: 30 * DAY as u32,
+        loan_interest_rate,
+        available_at: 0,
+        status: LoanStatus::Processing as u32,
+        created_at: U64(0),
+        updated_at: U64(0),
+        started_at: U64(0),
+        renewable: false,
+        min_on_time_rate: 0,
+        referrer_id: None,
+        variable_rate: false,
+        rate_index: U128(0),
+        allowed_borrower: None,
+    }
+}
+
+/// a tranched loan of 60 NEAR at 10% from account 1 and 40 NEAR at 20% from account 2
+fn sample_tranches() -> (Sale, Vec<Offer>) {
+    let mut sale = sample_sale(LoanConfig::Tranched);
+    sale.lender = "".to_string();
+    let offers = vec![
+        sample_offer(0, 1, 60 * NEAR, 1000),
+        sample_offer(1, 2, 40 * NEAR, 2000),
+    ];
+    (sale, offers)
+}
+
+/// liquidated at the deadline of sample_tranches
+fn liquidated_tranches() -> (Sale, Vec<Offer>) {
+    let (mut sale, mut offers) = sample_tranches();
+    sale.status = LoanStatus::Liquidated as u32;
+    sale.updated_at = U64(32 * DAY);
+    for offer in offers.iter_mut() {
+        offer.status = LoanStatus::Liquidated as u32;
+    }
+    (sale, offers)
+}
+
+#[test]
+fn test_liquidation_bounty() {
+    let sale = sample_sale(LoanConfig::Single);
+    let lender_ids = vec![account(1)];
+    // 1% of the principal
+    assert_eq!(
+        liquidation_bounty(&sale, &lender_ids, &account(3), 100, 10 * NEAR),
+        NEAR
+    );
+    // never more than the protocol fees
+    assert_eq!(
+        liquidation_bounty(&sale, &lender_ids, &account(3), 100, NEAR / 2),
+        NEAR / 2
+    );
+    assert_eq!(
+        liquidation_bounty(&sale, &lender_ids, &account(3), 0, 10 * NEAR),
+        0
+    );
+}
+
+#[test]
+fn test_liquidation_bounty_excludes_loan_parties() {
+    let sale = sample_sale(LoanConfig::Tranched);
+    let lender_ids = vec![account(1), account(2)];
+    for party_id in [account(0), account(1), account(2)].iter() {
+        assert_eq!(
+            liquidation_bounty(&sale, &lender_ids, party_id, 100, 10 * NEAR),
+            0
+        );
+    }
+}
+
+#[test]
+fn test_fee_stays_with_protocol() {
+    testing_env!(get_context(accounts(0)).build());
+    let mut contract = Contract::new(accounts(0), None);
+    let payoff = loan_payoff(
+        100 * NEAR,
+        30 * DAY as u32,
+        1000,
+        0,
+        30 * DAY,
+        default_fee_bps(),
+    );
+    // 1% of the principal on top of principal and interest
+    assert_eq!(payoff.fee, NEAR);
+    assert_eq!(
+        payoff.total - payoff.fee,
+        payoff.principal + payoff.interest
+    );
+
+    let sale = sample_sale(LoanConfig::Single);
+    contract.internal_collect_fee(&sale, None, &"near".to_string(), payoff.fee);
+    assert_eq!(contract.get_protocol_fees("near".to_string()).0, NEAR);
+}
+
+#[test]
+fn test_tranche_payoffs() {
+    let (sale, offers) = sample_tranches();
+    let payoffs = sale_payoffs(&sale, &offers, 30 * DAY, default_fee_bps(), 0);
+    assert_eq!(payoffs.len(), 2);
+    // each tranche is paid its own principal at its own rate
+    for (
+        LenderPayoff {
+            lender_id, payoff, ..
+        },
+        offer,
+    ) in payoffs.iter().zip(offers.iter())
+    {
+        let expected = loan_payoff(
+            offer.loan_principal_amount,
+            sale.loan_duration,
+            offer.loan_interest_rate,
+            0,
+            30 * DAY,
+            default_fee_bps(),
+        );
+        assert_eq!(lender_id, &offer.lender_id);
+        assert_eq!(payoff, &expected);
+    }
+    let total = sale_payoff(&sale, &offers, 30 * DAY, default_fee_bps(), 0);
+    assert_eq!(total.principal, sale.loan_principal_amount);
+    assert_eq!(total.fee, NEAR);
+}
+
+#[test]
+fn test_tranche_claims() {
+    let (sale, offers) = liquidated_tranches();
+    let claims = tranche_claims(&sale, &offers);
+    assert_eq!(claims.len(), 2);
+    for ((offer_id, lender_id, claim), offer) in claims.iter().zip(offers.iter()) {
+        let payoff = loan_payoff(
+            offer.loan_principal_amount,
+            sale.loan_duration,
+            offer.loan_interest_rate,
+            0,
+            30 * DAY,
+            default_fee_bps(),
+        );
+        assert_eq!(*offer_id, offer.offer_id);
+        assert_eq!(lender_id, &offer.lender_id);
+        // principal and full interest, no fee
+        assert_eq!(*claim, payoff.principal + payoff.interest);
+    }
+}
+
+#[test]
+fn test_tranche_buyout() {
+    let (sale, offers) = liquidated_tranches();
+    let claims = tranche_claims(&sale, &offers);
+    // an outsider pays every tranche
+    assert_eq!(buyout_payouts(&sale, &offers, &account(3), false), claims);
+    // a holder skips its own tranche, unless it is a note that may have changed hands
+    assert_eq!(
+        buyout_payouts(&sale, &offers, &account(1), false),
+        vec![claims[1].clone()]
+    );
+    assert_eq!(buyout_payouts(&sale, &offers, &account(1), true), claims);
+    // the buyout period keeps the full claims
+    let now = sale.updated_at.0 + TRANCHE_BUYOUT_PERIOD - 1;
+    assert_eq!(
+        claim_payouts(&sale, &offers, &account(1), false, now),
+        vec![claims[1].clone()]
+    );
+}
+
+#[test]
+fn test_tranche_auction_price() {
+    let total_claim = 120 * NEAR;
+    let reserve_price = 100 * NEAR;
+    let auction_start = 32 * DAY + TRANCHE_BUYOUT_PERIOD;
+    let price_at = |now| tranche_auction_price(total_claim, reserve_price, 32 * DAY, now);
+    assert_eq!(price_at(auction_start - 1), None);
+    assert_eq!(price_at(auction_start), Some(total_claim));
+    assert_eq!(
+        price_at(auction_start + TRANCHE_AUCTION_PERIOD / 2),
+        Some(110 * NEAR)
+    );
+    // the price stops at the reserve
+    assert_eq!(
+        price_at(auction_start + TRANCHE_AUCTION_PERIOD),
+        Some(reserve_price)
+    );
+    assert_eq!(
+        price_at(auction_start + 2 * TRANCHE_AUCTION_PERIOD),
+        Some(reserve_price)
+    );
+    assert_eq!(
+        tranche_auction_price(0, 0, 32 * DAY, auction_start + TRANCHE_AUCTION_PERIOD),
+        Some(0)
+    );
+}
+
+#[test]
+fn test_tranche_auction_splits_pro_rata() {
+    let claims_of = |claims: &[u128]| -> Vec<(u32, AccountId, u128)> {
+        claims
+            .iter()
+            .enumerate()
+            .map(|(i, claim)| (i as u32, account(i + 1), *claim))
+            .collect()
+    };
+    let claims = claims_of(&[300, 100]);
+    // the dust of the rounding goes to the last tranche
+    assert_eq!(
+        pro_rata_payouts(claims, 101),
+        vec![(0, account(1), 75), (1, account(2), 26)]
+    );
+    // no zero transfers, nothing to split without claims
+    assert_eq!(
+        pro_rata_payouts(claims_of(&[300, 100]), 1),
+        vec![(1, account(2), 1)]
+    );
+    assert!(pro_rata_payouts(vec![], 100).is_empty());
+
+    let (sale, offers) = liquidated_tranches();
+    let claims = tranche_claims(&sale, &offers);
+    let total_claim: u128 = claims.iter().map(|(_, _, claim)| claim).sum();
+    let now = sale.updated_at.0 + TRANCHE_BUYOUT_PERIOD + TRANCHE_AUCTION_PERIOD / 2;
+    // after the buyout period a holder pays the auction price, its own tranche included
+    let payouts = claim_payouts(&sale, &offers, &account(1), false, now);
+    assert_eq!(payouts.len(), 2);
+    let price: u128 = payouts.iter().map(|(_, _, payout)| payout).sum();
+    let reserve_price = tranche_reserve_price(&offers);
+    assert_eq!(reserve_price, 100 * NEAR);
+    assert_eq!(price, reserve_price + (total_claim - reserve_price + 1) / 2);
+    assert_eq!(
+        payouts[0].2,
+        mul_div(price, claims[0].2, total_claim, Rounding::Down).unwrap()
+    );
+}
+
+#[test]
+fn test_reward_amount_saturates() {
+    // one reward unit per principal unit and second
+    assert_eq!(
+        reward_amount(100, DAY, REWARD_RATE_SCALE),
+        100 * DAY as u128
+    );
+    // principal × seconds fits, × rate does not
+    assert_eq!(
+        reward_amount(u128::MAX / 2, 2, REWARD_RATE_SCALE),
+        u128::MAX - 1
+    );
+    assert_eq!(reward_amount(u128::MAX, DAY, REWARD_RATE_SCALE), u128::MAX);
+}
+
+fn sample_rate_model() -> RateModel {
+    RateModel {
+        base_rate: 200,
+        slope1: 1000,
+        slope2: 5000,
+        kink: 8000,
+    }
+}
+
+#[test]
+fn test_rate_model_kink() {
+    let model = sample_rate_model();
+    // below the kink the rate climbs slope1 over 0..kink
+    assert_eq!(model.rate(0), 200);
+    assert_eq!(model.rate(4000), 700);
+    assert_eq!(model.rate(8000), 1200);
+    // above it slope2 over kink..10000
+    assert_eq!(model.rate(9000), 3700);
+    assert_eq!(model.rate(10000), 6200);
+    assert_eq!(model.rate(20000), 6200);
+}
+
+#[test]
+fn test_rate_pool_index_matches_payoff() {
+    let model = sample_rate_model();
+    let mut pool = RatePool {
+        supplied: 100 * NEAR,
+        borrowed: 80 * NEAR,
+        index: RATE_INDEX_SCALE,
+        updated_at: 0,
+        rate: 0,
+    };
+    let year = 365 * DAY;
+    assert_eq!(pool.index_at(year), RATE_INDEX_SCALE);
+    assert_eq!(pool.utilization(), 8000);
+    pool.rate = model.rate(pool.utilization());
+    // a year at 12%
+    let index = pool.index_at(year);
+    assert_eq!(index, RATE_INDEX_SCALE * 112 / 100);
+    assert_eq!(pool.index_at(0), RATE_INDEX_SCALE);
+
+    let payoff = variable_payoff(10 * NEAR, RATE_INDEX_SCALE, index, 100);
+    assert_eq!(payoff.interest, 10 * NEAR * 12 / 100);
+    assert_eq!(payoff.fee, 10 * NEAR / 100);
+    assert_eq!(payoff.total, 10 * NEAR * 112 / 100 + payoff.fee);
+    // a position lent out half way owes the growth since its start index
+    let start_index = pool.index_at(year / 2);
+    let payoff = variable_payoff(10 * NEAR, start_index, index, 0);
+    assert_eq!(
+        payoff.total,
+        mul_div(10 * NEAR, 112, 106, Rounding::Down).unwrap()
+    );
+}
+
+#[test]
+fn test_relist_refunds_open_offers() {
+    testing_env!(get_context(accounts(0)).build());
+    let mut contract = Contract::new(accounts(4), None);
+    let contract_and_token_id = format!("nft.near{}1", DELIMETER);
+    contract
+        .storage_deposits
+        .insert(&account(0), &STORAGE_PER_SALE);
+    contract
+        .storage_deposits
+        .insert(&account(2), &STORAGE_PER_OFFER);
+    // an offer left open on a loan that was paid back
+    let mut sale = sample_sale(LoanConfig::Single);
+    sale.status = LoanStatus::Done as u32;
+    contract.sales.insert(&contract_and_token_id, &sale);
+    let mut offer = sample_offer(0, 2, 50 * NEAR, 1000);
+    offer.status = LoanStatus::Open as u32;
+    contract.internal_add_offer(&contract_and_token_id, offer);
+    assert_eq!(contract.storage_deposits.get(&account(2)), Some(0));
+
+    let msg = near_sdk::serde_json::json!({
+        "loan_principal_amount": U128(100 * NEAR),
+        "loan_duration": 30 * DAY,
+        "loan_currency": "near",
+        "loan_interest_rate": 1000,
+        "loan_config": LoanConfig::Single as u32,
+        "available_at": 0,
+    })
+    .to_string();
+    contract.internal_add_sale(
+        "nft.near".to_string(),
+        "1".to_string(),
+        account(0),
+        1,
+        &msg,
+        false,
+    );
+    // the offer was refunded and its storage given back before the ids start over
+    assert_eq!(
+        contract.storage_deposits.get(&account(2)),
+        Some(STORAGE_PER_OFFER)
+    );
+    assert!(contract.internal_offers(&contract_and_token_id).is_empty());
+    let sale = contract.sales.get(&contract_and_token_id).unwrap();
+    assert_eq!(sale.status, LoanStatus::Open as u32);
+}
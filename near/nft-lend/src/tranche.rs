@@ -0,0 +1,298 @@
+use crate::*;
+use pawn_interest::{mul_div, Rounding};
+
+/// tranched loans, see LoanConfig::Tranched. The NFT of a defaulted tranched loan stays in
+/// escrow until someone buys out every tranche claim. If nobody does within
+/// TRANCHE_BUYOUT_PERIOD, the NFT goes to auction: its price falls from the sum of the claims
+/// to the sum of the tranche principals over TRANCHE_AUCTION_PERIOD and stays there, anyone
+/// can buy at the current price and the proceeds are split over the tranches by claim
+
+/// seconds after the liquidation during which the NFT sells for the full claims
+pub const TRANCHE_BUYOUT_PERIOD: u64 = 7 * 86400;
+/// seconds the auction price then takes to fall to the reserve
+pub const TRANCHE_AUCTION_PERIOD: u64 = 7 * 86400;
+
+/// (offer_id, lender_id, claim) of each tranche of a liquidated tranched loan
+pub(crate) fn tranche_claims(sale: &Sale, offers: &[Offer]) -> Vec<(u32, AccountId, u128)> {
+    offers
+        .iter()
+        .filter(|offer| offer.status == LoanStatus::Liquidated as u32)
+        .map(|offer| {
+            (
+                offer.offer_id,
+                offer.lender_id.clone(),
+                tranche_claim(sale, offer),
+            )
+        })
+        .collect()
+}
+
+/// what `buyer_id` has to pay every other holder of a liquidated tranched loan, a buyer
+/// skips its own tranches unless they are held as notes, which may have changed hands
+pub(crate) fn buyout_payouts(
+    sale: &Sale,
+    offers: &[Offer],
+    buyer_id: &AccountId,
+    notes_enabled: bool,
+) -> Vec<(u32, AccountId, u128)> {
+    tranche_claims(sale, offers)
+        .into_iter()
+        .filter(|(_, lender_id, _)| notes_enabled || lender_id != buyer_id)
+        .collect()
+}
+
+/// the principal of the liquidated tranches, the auction never sells below it
+pub(crate) fn tranche_reserve_price(offers: &[Offer]) -> u128 {
+    offers
+        .iter()
+        .filter(|offer| offer.status == LoanStatus::Liquidated as u32)
+        .map(|offer| offer.loan_principal_amount)
+        .sum()
+}
+
+/// auction price at `now` (seconds) of an NFT whose tranches claim `total_claim`, from there
+/// down to `reserve_price`. None while the buyout period after `liquidated_at` runs
+pub(crate) fn tranche_auction_price(
+    total_claim: u128,
+    reserve_price: u128,
+    liquidated_at: u64,
+    now: u64,
+) -> Option<u128> {
+    let auction_start = liquidated_at + TRANCHE_BUYOUT_PERIOD;
+    if now < auction_start {
+        return None;
+    }
+    let remaining = TRANCHE_AUCTION_PERIOD - min(now - auction_start, TRANCHE_AUCTION_PERIOD);
+    let reserve_price = min(reserve_price, total_claim);
+    Some(
+        reserve_price
+            + mul_div(
+                total_claim - reserve_price,
+                remaining as u128,
+                TRANCHE_AUCTION_PERIOD as u128,
+                Rounding::Up,
+            )
+            .expect("auction price overflow"),
+    )
+}
+
+/// `proceeds` split over `claims` by size, the rounding dust goes to the last claim. Zero
+/// shares are left out, ft_transfer rejects them
+pub(crate) fn pro_rata_payouts(
+    claims: Vec<(u32, AccountId, u128)>,
+    proceeds: u128,
+) -> Vec<(u32, AccountId, u128)> {
+    let total_claim: u128 = claims.iter().map(|(_, _, claim)| claim).sum();
+    if total_claim == 0 {
+        return vec![];
+    }
+    let count = claims.len();
+    let mut paid = 0;
+    let mut payouts = vec![];
+    for (i, (offer_id, lender_id, claim)) in claims.into_iter().enumerate() {
+        let share = if i + 1 == count {
+            proceeds - paid
+        } else {
+            mul_div(proceeds, claim, total_claim, Rounding::Down).expect("payout overflow")
+        };
+        paid += share;
+        if share > 0 {
+            payouts.push((offer_id, lender_id, share));
+        }
+    }
+    payouts
+}
+
+/// what `buyer_id` pays each tranche of a liquidated tranched loan at `now`, the full claims
+/// of the others during the buyout period and the auction price split over all of them after.
+/// A liquidated sale is not updated again until its claim is settled, so updated_at is when
+/// it was liquidated
+pub(crate) fn claim_payouts(
+    sale: &Sale,
+    offers: &[Offer],
+    buyer_id: &AccountId,
+    notes_enabled: bool,
+    now: u64,
+) -> Vec<(u32, AccountId, u128)> {
+    let claims = tranche_claims(sale, offers);
+    let total_claim: u128 = claims.iter().map(|(_, _, claim)| claim).sum();
+    let reserve_price = tranche_reserve_price(offers);
+    match tranche_auction_price(total_claim, reserve_price, sale.updated_at.0, now) {
+        None => buyout_payouts(sale, offers, buyer_id, notes_enabled),
+        Some(price) => pro_rata_payouts(claims, price),
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// borrower accepts several open offers that together fill loan_principal_amount
+    pub fn accept_tranches(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        offer_ids: Vec<u32>,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert_borrower(&sale);
+        assert!(
+            sale.status == LoanStatus::Open as u32,
+            "Loan is in proccessing "
+        );
+        assert!(
+            sale.loan_config == LoanConfig::Tranched as u32,
+            "Sale is not tranched"
+        );
+        let now = U64(env::block_timestamp() / 1000000000);
+        let mut principal: u128 = 0;
+        let mut accepted = 0;
+        for mut offer in self.internal_offers(&contract_and_token_id) {
+            if offer.status != LoanStatus::Open as u32 {
+                continue;
+            }
+            offer.updated_at = now;
+            if offer_ids.contains(&offer.offer_id) {
+                self.assert_on_time_rate(&sale.owner_id, &offer);
+                assert_allowed_borrower(&offer, &sale.owner_id);
+                offer.status = LoanStatus::Processing as u32;
+                offer.started_at = now;
+                principal += offer.loan_principal_amount;
+                accepted += 1;
+            } else {
+                self.internal_send(
+                    &sale.loan_currency,
+                    offer.lender_id.clone(),
+                    offer.loan_principal_amount,
+                    "refund from market",
+                );
+                offer.status = LoanStatus::Canceled as u32;
+                self.internal_release_offer_storage(&offer.lender_id);
+            }
+            self.internal_update_offer(&contract_and_token_id, &offer);
+        }
+        assert!(accepted == offer_ids.len(), "Offer is not open");
+        assert!(
+            principal == sale.loan_principal_amount,
+            "Tranches add up to {}, loan principal amount is {}",
+            principal,
+            sale.loan_principal_amount
+        );
+        sale.status = LoanStatus::Processing as u32;
+        sale.started_at = now;
+        sale.updated_at = now;
+        sale.lender = "".to_string();
+        self.sales.insert(&contract_and_token_id, &sale);
+        self.process_purchase(
+            nft_contract_id,
+            token_id,
+            sale.loan_currency,
+            U128(principal),
+            sale.approval_id,
+            sale.owner_id,
+        );
+    }
+
+    /// buys the NFT of a liquidated tranched loan, during the buyout period each other
+    /// tranche holder gets its principal and full interest, after it the auction price is
+    /// split over all of them, for ft currencies see ft_on_transfer "buyout_claim"
+    #[payable]
+    pub fn buyout_claim_by_near(&mut self, nft_contract_id: AccountId, token_id: String) {
+        let buyer_id = env::predecessor_account_id();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let refund = self.internal_buyout_claim(
+            contract_and_token_id,
+            buyer_id.clone(),
+            "near".to_string(),
+            env::attached_deposit(),
+        );
+        if refund > 0 {
+            Promise::new(buyer_id).transfer(refund);
+        }
+    }
+
+    /// views
+
+    pub fn get_buyout_price(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        buyer_id: AccountId,
+    ) -> U128 {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let offers = self.internal_offers(&contract_and_token_id);
+        U128(
+            claim_payouts(
+                &sale,
+                &offers,
+                &buyer_id,
+                sale.note_contract_id.is_some(),
+                env::block_timestamp() / 1000000000,
+            )
+            .iter()
+            .map(|(_, _, claim)| claim)
+            .sum(),
+        )
+    }
+}
+
+impl Contract {
+    /// returns the part of `amount` above the buyout price
+    pub(crate) fn internal_buyout_claim(
+        &mut self,
+        contract_and_token_id: ContractAndTokenId,
+        buyer_id: AccountId,
+        ft_token_id: AccountId,
+        amount: u128,
+    ) -> u128 {
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        assert!(ft_token_id == sale.loan_currency, "ft_token is invalid");
+        assert!(
+            sale.status == LoanStatus::Liquidated as u32
+                && sale.loan_config == LoanConfig::Tranched as u32,
+            "No tranche claim to buy out"
+        );
+        let offers = self.internal_offers(&contract_and_token_id);
+        let payouts = claim_payouts(
+            &sale,
+            &offers,
+            &buyer_id,
+            sale.note_contract_id.is_some(),
+            env::block_timestamp() / 1000000000,
+        );
+        let price: u128 = payouts.iter().map(|(_, _, claim)| claim).sum();
+        assert!(
+            amount >= price,
+            "Buyout price is {}, paid {}",
+            price,
+            amount
+        );
+        let mut settled = false;
+        for mut offer in offers {
+            if offer.status == LoanStatus::Liquidated as u32 {
+                offer.status = LoanStatus::Done as u32;
+                offer.updated_at = U64(env::block_timestamp() / 1000000000);
+                self.internal_update_offer(&contract_and_token_id, &offer);
+                self.internal_release_offer_storage(&offer.lender_id);
+                settled = true;
+            }
+        }
+        assert!(settled, "Tranche claim is already settled");
+        sale.lender = buyer_id.clone();
+        sale.updated_at = U64(env::block_timestamp() / 1000000000);
+        self.sales.insert(&contract_and_token_id, &sale);
+
+        for (offer_id, lender_id, claim) in payouts {
+            self.internal_pay_position(
+                &sale,
+                offer_id,
+                lender_id,
+                ft_token_id.clone(),
+                claim,
+                "tranche buyout",
+            );
+        }
+        self.process_liquidate_loan(sale.nft_contract_id, sale.token_id, buyer_id);
+        amount - price
+    }
+}
@@ -0,0 +1,267 @@
+use crate::*;
+use pawn_interest::{mul_div, Payoff, Rounding, BPS_DENOMINATOR};
+
+/// variable-rate loans: a lender can make an "offer" with variable_rate instead of a fixed
+/// loan_interest_rate. The variable offers of a collection in a currency form its pool,
+/// utilization is the share of their principal lent out and sets the rate through the
+/// currency's RateModel, offers on other collections do not move it. Interest accrues into a
+/// cumulative index, each loan owes principal × index at pay back / index at its start
+
+/// RatePool.index is scaled by RATE_INDEX_SCALE, it starts at 1
+pub const RATE_INDEX_SCALE: u128 = 1_000_000_000_000_000_000;
+const SECONDS_PER_YEAR: u128 = 365 * 86400;
+
+/// key of the pool of the variable offers on `nft_contract_id` in `ft_token_id`
+pub(crate) fn rate_pool_id(nft_contract_id: &str, ft_token_id: &str) -> String {
+    format!("{}{}{}", nft_contract_id, DELIMETER, ft_token_id)
+}
+
+/// yearly rate in basis points by utilization, linear up to kink and steeper after it
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RateModel {
+    pub base_rate: u32,
+    /// rate added between 0 and kink utilization
+    pub slope1: u32,
+    /// rate added between kink and full utilization
+    pub slope2: u32,
+    /// utilization in basis points
+    pub kink: u32,
+}
+
+impl RateModel {
+    pub fn rate(&self, utilization: u32) -> u32 {
+        let utilization = min(utilization, BPS_DENOMINATOR as u32) as u64;
+        let kink = self.kink as u64;
+        let rate = if utilization <= kink {
+            self.base_rate as u64 + self.slope1 as u64 * utilization / kink
+        } else {
+            self.base_rate as u64
+                + self.slope1 as u64
+                + self.slope2 as u64 * (utilization - kink) / (BPS_DENOMINATOR as u64 - kink)
+        };
+        rate as u32
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RatePool {
+    /// principal of the variable offers that are open or lent out
+    pub supplied: u128,
+    /// principal of the variable offers lent out
+    pub borrowed: u128,
+    pub index: u128,
+    /// seconds, last accrual of index
+    pub updated_at: u64,
+    /// yearly rate in basis points index grows at since updated_at, from the RateModel and
+    /// utilization of that accrual
+    pub rate: u32,
+}
+
+impl Default for RatePool {
+    fn default() -> Self {
+        RatePool {
+            supplied: 0,
+            borrowed: 0,
+            index: RATE_INDEX_SCALE,
+            updated_at: env::block_timestamp() / 1000000000,
+            rate: 0,
+        }
+    }
+}
+
+impl RatePool {
+    /// in basis points
+    pub fn utilization(&self) -> u32 {
+        if self.supplied == 0 {
+            0
+        } else {
+            (self.borrowed * BPS_DENOMINATOR / self.supplied) as u32
+        }
+    }
+
+    /// index at `at` (seconds) at the current rate
+    pub(crate) fn index_at(&self, at: u64) -> u128 {
+        let elapsed = at.saturating_sub(self.updated_at) as u128;
+        let growth = mul_div(
+            self.index,
+            self.rate as u128 * elapsed,
+            SECONDS_PER_YEAR * BPS_DENOMINATOR,
+            Rounding::Up,
+        )
+        .expect("rate index overflow");
+        self.index + growth
+    }
+}
+
+/// payoff of a variable-rate position, `start_index` is the pool index when it was lent out
+pub(crate) fn variable_payoff(
+    loan_principal_amount: u128,
+    start_index: u128,
+    index: u128,
+    fee_bps: u32,
+) -> Payoff {
+    let owed = mul_div(loan_principal_amount, index, start_index, Rounding::Down)
+        .expect("pay amount overflow");
+    let fee = mul_div(
+        loan_principal_amount,
+        fee_bps as u128,
+        BPS_DENOMINATOR,
+        Rounding::Down,
+    )
+    .expect("fee overflow");
+    Payoff {
+        principal: loan_principal_amount,
+        interest: owed - loan_principal_amount,
+        discounted_interest: 0,
+        fee,
+        total: owed + fee,
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// only owner, None stops new variable offers in the currency. Each pool of the currency
+    /// keeps its rate until its next accrual, when an offer or loan of the collection changes
+    pub fn set_rate_model(&mut self, ft_token_id: AccountId, rate_model: Option<RateModel>) {
+        self.assert_owner();
+        assert!(
+            self.ft_token_ids.contains(&ft_token_id),
+            "Token {} not supported by this market",
+            ft_token_id
+        );
+        match rate_model {
+            Some(rate_model) => {
+                assert!(
+                    rate_model.kink > 0 && rate_model.kink < BPS_DENOMINATOR as u32,
+                    "kink must be between 0 and 10000"
+                );
+                self.rate_models.insert(&ft_token_id, &rate_model);
+            }
+            None => {
+                self.rate_models.remove(&ft_token_id);
+            }
+        }
+    }
+
+    /// views
+
+    pub fn get_rate_model(&self, ft_token_id: AccountId) -> Option<RateModel> {
+        self.rate_models.get(&ft_token_id)
+    }
+
+    /// the pool of a collection in a currency with its index accrued to now
+    pub fn get_rate_pool(&self, nft_contract_id: AccountId, ft_token_id: AccountId) -> RatePool {
+        let mut pool = self
+            .rate_pools
+            .get(&rate_pool_id(&nft_contract_id, &ft_token_id))
+            .unwrap_or_default();
+        let now = env::block_timestamp() / 1000000000;
+        pool.index = pool.index_at(now);
+        pool.updated_at = now;
+        pool
+    }
+
+    /// yearly rate in basis points variable-rate loans on the collection in the currency
+    /// accrue now
+    pub fn get_variable_rate(&self, nft_contract_id: AccountId, ft_token_id: AccountId) -> u32 {
+        self.rate_pools
+            .get(&rate_pool_id(&nft_contract_id, &ft_token_id))
+            .map_or(0, |pool| pool.rate)
+    }
+}
+
+impl Contract {
+    /// rate of the loan currency's RateModel at the pool's utilization
+    fn internal_pool_rate(&self, ft_token_id: &AccountId, pool: &RatePool) -> u32 {
+        self.rate_models
+            .get(ft_token_id)
+            .map_or(0, |model| model.rate(pool.utilization()))
+    }
+
+    /// index of the sale's pool at `at` (seconds), not before its last accrual
+    pub(crate) fn internal_rate_index(&self, sale: &Sale, at: u64) -> u128 {
+        self.rate_pools
+            .get(&rate_pool_id(&sale.nft_contract_id, &sale.loan_currency))
+            .unwrap_or_default()
+            .index_at(at)
+    }
+
+    /// brings the index of the sale's pool up to now, before anything changes the utilization
+    pub(crate) fn internal_accrue_rate_index(&mut self, sale: &Sale) -> u128 {
+        let pool_id = rate_pool_id(&sale.nft_contract_id, &sale.loan_currency);
+        let mut pool = match self.rate_pools.get(&pool_id) {
+            Some(pool) => pool,
+            None => return RATE_INDEX_SCALE,
+        };
+        let now = env::block_timestamp() / 1000000000;
+        pool.index = pool.index_at(now);
+        pool.updated_at = now;
+        pool.rate = self.internal_pool_rate(&sale.loan_currency, &pool);
+        self.rate_pools.insert(&pool_id, &pool);
+        pool.index
+    }
+
+    /// a variable offer needs a rate model and a single-lender fixed-term sale
+    pub(crate) fn assert_variable_offer(&self, sale: &Sale) {
+        if let Err(message) = self.check_variable_offer(sale) {
+            env::panic(message.as_bytes());
+        }
+    }
+
+    pub(crate) fn check_variable_offer(&self, sale: &Sale) -> Result<(), String> {
+        if self.rate_models.get(&sale.loan_currency).is_none() {
+            return Err(format!("No variable rate in {}", sale.loan_currency));
+        }
+        if sale.loan_config != LoanConfig::Single as u32 {
+            return Err("Variable rate needs a single-lender sale".to_string());
+        }
+        Ok(())
+    }
+
+    /// moves a variable offer's principal in and out of its sale's pool as `old` becomes
+    /// `offer`, returns the index the position accrues from
+    pub(crate) fn internal_track_variable_offer(
+        &mut self,
+        sale: &Sale,
+        old: Option<&Offer>,
+        offer: &Offer,
+    ) -> U128 {
+        let is_supplied = |offer: &Offer| {
+            offer.status == LoanStatus::Open as u32 || offer.status == LoanStatus::Processing as u32
+        };
+        let is_borrowed = |offer: &Offer| offer.status == LoanStatus::Processing as u32;
+        let index = self.internal_accrue_rate_index(sale);
+        let pool_id = rate_pool_id(&sale.nft_contract_id, &sale.loan_currency);
+        let mut pool = self.rate_pools.get(&pool_id).unwrap_or_default();
+        if let Some(old) = old {
+            if is_supplied(old) {
+                pool.supplied -= old.loan_principal_amount;
+            }
+            if is_borrowed(old) {
+                pool.borrowed -= old.loan_principal_amount;
+            }
+        }
+        if is_supplied(offer) {
+            pool.supplied += offer.loan_principal_amount;
+        }
+        if is_borrowed(offer) {
+            pool.borrowed += offer.loan_principal_amount;
+        }
+        // the new utilization sets the rate from here on
+        pool.rate = self.internal_pool_rate(&sale.loan_currency, &pool);
+        self.rate_pools.insert(&pool_id, &pool);
+
+        if !is_borrowed(offer) {
+            return U128(0);
+        }
+        // a position accrues from its (re)start, see rollover_loan
+        match old {
+            Some(old) if is_borrowed(old) && old.started_at.0 == offer.started_at.0 => {
+                old.rate_index
+            }
+            _ => U128(index),
+        }
+    }
+}
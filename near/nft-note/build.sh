@@ -0,0 +1,2 @@
+#!/bin/bash
+set -e && RUSTFLAGS='-C link-arg=-s' cargo build --target wasm32-unknown-unknown --release 
\ No newline at end of file
@@ -0,0 +1,4 @@
+export NEAR_ENV=mainnet
+ID=$1
+near login
+./build.sh && near deploy --wasmFile target/wasm32-unknown-unknown/release/nft_note.wasm --accountId $ID
\ No newline at end of file
@@ -0,0 +1,257 @@
+//! Interest and fee math shared by the NEAR and Solana nft-lend programs.
+//!
+//! A loan runs for `duration` seconds at `interest_rate` basis points per year.
+//! Repaying at `pay_at` costs:
+//!
+//! * the principal,
+//! * the full-rate interest for the time the loan was actually used,
+//! * `early_repayment_bps` of the interest for the unused rest of the term
+//!   (the contracts have always charged 50%, i.e. 5000 bps),
+//! * the protocol fee, `fee_bps` of the principal.
+//!
+//! Every step uses checked arithmetic with a 256-bit intermediate product and
+//! rounds according to `InterestConfig::rounding`, so the same inputs give the
+//! same result on every chain. The steps round where the contracts always did:
+//! the yearly interest `principal * rate` first, then each interest part, so
+//! the default config gives exactly the amounts the contracts charged before
+//! this crate.
+
+#![no_std]
+
+mod math;
+
+pub use crate::math::mul_div;
+
+pub const DAY_SECS: u64 = 86_400;
+pub const BPS_DENOMINATOR: u128 = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MathError {
+    Overflow,
+    DivisionByZero,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// in favour of the borrower
+    Down,
+    /// in favour of the lender / protocol
+    Up,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Accrual {
+    /// whole days, a started day is charged in full and the term is at least one day. A
+    /// repayment at or before the start is charged the whole term
+    Daily,
+    /// pro-rata per second of the term
+    PerSecond,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterestConfig {
+    pub accrual: Accrual,
+    pub days_per_year: u64,
+    /// share of the interest for the unused rest of the term still charged on early repayment
+    pub early_repayment_bps: u32,
+    /// protocol fee, share of the principal
+    pub fee_bps: u32,
+    pub rounding: Rounding,
+}
+
+impl Default for InterestConfig {
+    /// the rules nft-lend has used so far: daily accrual on a 365-day year,
+    /// 50% interest for the unused days and a 1% fee, rounded down
+    fn default() -> Self {
+        InterestConfig {
+            accrual: Accrual::Daily,
+            days_per_year: 365,
+            early_repayment_bps: 5000,
+            fee_bps: 100,
+            rounding: Rounding::Down,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoanTerms {
+    pub principal: u128,
+    /// seconds
+    pub duration: u64,
+    /// basis points per year
+    pub interest_rate: u32,
+    /// unix seconds
+    pub started_at: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Payoff {
+    pub principal: u128,
+    /// full-rate interest for the elapsed part of the term
+    pub interest: u128,
+    /// reduced interest for the unused rest of the term
+    pub discounted_interest: u128,
+    pub fee: u128,
+    pub total: u128,
+}
+
+impl InterestConfig {
+    pub fn fee(&self, principal: u128) -> Result<u128, MathError> {
+        mul_div(
+            principal,
+            self.fee_bps as u128,
+            BPS_DENOMINATOR,
+            self.rounding,
+        )
+    }
+
+    /// (elapsed, term, units per year) in the unit of the accrual mode
+    fn periods(&self, loan: &LoanTerms, pay_at: u64) -> Result<(u128, u128, u128), MathError> {
+        let elapsed_secs = pay_at.saturating_sub(loan.started_at);
+        match self.accrual {
+            Accrual::Daily => {
+                let term = core::cmp::max(loan.duration / DAY_SECS, 1);
+                let elapsed = if elapsed_secs == 0 || elapsed_secs >= loan.duration {
+                    term
+                } else {
+                    core::cmp::min(elapsed_secs / DAY_SECS + 1, term)
+                };
+                Ok((elapsed as u128, term as u128, self.days_per_year as u128))
+            }
+            Accrual::PerSecond => {
+                let elapsed = core::cmp::min(elapsed_secs, loan.duration);
+                let year = (self.days_per_year as u128)
+                    .checked_mul(DAY_SECS as u128)
+                    .ok_or(MathError::Overflow)?;
+                Ok((elapsed as u128, loan.duration as u128, year))
+            }
+        }
+    }
+
+    /// amount due when the loan is repaid at `pay_at` (unix seconds)
+    pub fn payoff(&self, loan: &LoanTerms, pay_at: u64) -> Result<Payoff, MathError> {
+        let (elapsed, term, year) = self.periods(loan, pay_at)?;
+        let yearly_interest = mul_div(
+            loan.principal,
+            loan.interest_rate as u128,
+            BPS_DENOMINATOR,
+            self.rounding,
+        )?;
+
+        let interest = mul_div(yearly_interest, elapsed, year, self.rounding)?;
+        let unused_interest = mul_div(yearly_interest, term - elapsed, year, self.rounding)?;
+        let discounted_interest = mul_div(
+            unused_interest,
+            self.early_repayment_bps as u128,
+            BPS_DENOMINATOR,
+            self.rounding,
+        )?;
+        let fee = self.fee(loan.principal)?;
+        let total = loan
+            .principal
+            .checked_add(interest)
+            .and_then(|v| v.checked_add(discounted_interest))
+            .and_then(|v| v.checked_add(fee))
+            .ok_or(MathError::Overflow)?;
+
+        Ok(Payoff {
+            principal: loan.principal,
+            interest,
+            discounted_interest,
+            fee,
+            total,
+        })
+    }
+
+    /// principal plus the interest for the whole term, without the fee
+    pub fn amount_at_maturity(&self, loan: &LoanTerms) -> Result<u128, MathError> {
+        let payoff = self.payoff(loan, loan.started_at.saturating_add(loan.duration))?;
+        payoff
+            .principal
+            .checked_add(payoff.interest)
+            .ok_or(MathError::Overflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEAR: u128 = 1_000_000_000_000_000_000_000_000;
+
+    fn loan(duration: u64) -> LoanTerms {
+        LoanTerms {
+            principal: 1000 * NEAR,
+            duration,
+            interest_rate: 3650,
+            started_at: 1_000_000,
+        }
+    }
+
+    #[test]
+    fn daily_charges_started_days_and_half_of_the_rest() {
+        let config = InterestConfig::default();
+        // 10-day loan at 36.5% a year is 1 NEAR of interest per day
+        let payoff = config
+            .payoff(&loan(10 * DAY_SECS), 1_000_000 + DAY_SECS + 1)
+            .unwrap();
+        assert_eq!(payoff.interest, 2 * NEAR);
+        assert_eq!(payoff.discounted_interest, 4 * NEAR);
+        assert_eq!(payoff.fee, 10 * NEAR);
+        assert_eq!(payoff.total, 1016 * NEAR);
+    }
+
+    #[test]
+    fn per_second_accrues_pro_rata() {
+        let config = InterestConfig {
+            accrual: Accrual::PerSecond,
+            early_repayment_bps: 0,
+            ..InterestConfig::default()
+        };
+        let payoff = config
+            .payoff(&loan(10 * DAY_SECS), 1_000_000 + DAY_SECS / 2)
+            .unwrap();
+        assert_eq!(payoff.interest, NEAR / 2);
+        assert_eq!(payoff.discounted_interest, 0);
+    }
+
+    #[test]
+    fn rounding_is_explicit() {
+        let terms = LoanTerms {
+            principal: 1,
+            duration: DAY_SECS,
+            interest_rate: 1,
+            started_at: 0,
+        };
+        let down = InterestConfig::default();
+        let up = InterestConfig {
+            rounding: Rounding::Up,
+            ..down
+        };
+        assert_eq!(down.payoff(&terms, DAY_SECS).unwrap().total, 1);
+        assert_eq!(up.payoff(&terms, DAY_SECS).unwrap().total, 3);
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let terms = LoanTerms {
+            principal: u128::MAX,
+            duration: 365 * DAY_SECS,
+            interest_rate: 10000,
+            started_at: 0,
+        };
+        assert_eq!(
+            InterestConfig::default().payoff(&terms, 0),
+            Err(MathError::Overflow)
+        );
+    }
+
+    #[test]
+    fn maturity_amount_excludes_fee() {
+        let config = InterestConfig::default();
+        assert_eq!(
+            config.amount_at_maturity(&loan(10 * DAY_SECS)).unwrap(),
+            1010 * NEAR
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Checked integer helpers.
+
+use crate::{MathError, Rounding};
+
+const LOW_MASK: u128 = u64::MAX as u128;
+
+/// 256-bit product of two u128 as (high, low) words
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    let (a0, a1) = (a & LOW_MASK, a >> 64);
+    let (b0, b1) = (b & LOW_MASK, b >> 64);
+    let p00 = a0 * b0;
+    let p01 = a0 * b1;
+    let p10 = a1 * b0;
+    let p11 = a1 * b1;
+    // at most 3 * (2^64 - 1), fits easily
+    let mid = (p00 >> 64) + (p01 & LOW_MASK) + (p10 & LOW_MASK);
+    let low = (p00 & LOW_MASK) | (mid << 64);
+    let high = p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64);
+    (high, low)
+}
+
+/// `a * b / denominator` without intermediate overflow, rounded as asked.
+///
+/// Fails with `DivisionByZero` when `denominator` is 0 and with `Overflow` when
+/// the quotient does not fit in a u128.
+pub fn mul_div(a: u128, b: u128, denominator: u128, rounding: Rounding) -> Result<u128, MathError> {
+    if denominator == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    let (high, low) = full_mul(a, b);
+    if high >= denominator {
+        return Err(MathError::Overflow);
+    }
+    // schoolbook long division of (high, low) by denominator, one bit at a time
+    let mut remainder = high;
+    let mut quotient: u128 = 0;
+    for i in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> i) & 1);
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1 << i;
+        }
+    }
+    match rounding {
+        Rounding::Down => Ok(quotient),
+        Rounding::Up if remainder == 0 => Ok(quotient),
+        Rounding::Up => quotient.checked_add(1).ok_or(MathError::Overflow),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_matches_native_when_it_fits() {
+        assert_eq!(mul_div(10, 7, 3, Rounding::Down), Ok(23));
+        assert_eq!(mul_div(10, 7, 3, Rounding::Up), Ok(24));
+        assert_eq!(mul_div(9, 7, 3, Rounding::Up), Ok(21));
+        assert_eq!(mul_div(0, 7, 3, Rounding::Up), Ok(0));
+    }
+
+    #[test]
+    fn mul_div_uses_wide_intermediate() {
+        let big = u128::MAX / 3;
+        assert_eq!(mul_div(big, 6, 6, Rounding::Down), Ok(big));
+        assert_eq!(
+            mul_div(u128::MAX, u128::MAX, u128::MAX, Rounding::Down),
+            Ok(u128::MAX)
+        );
+    }
+
+    #[test]
+    fn mul_div_reports_errors() {
+        assert_eq!(
+            mul_div(1, 1, 0, Rounding::Down),
+            Err(MathError::DivisionByZero)
+        );
+        assert_eq!(
+            mul_div(u128::MAX, 2, 1, Rounding::Down),
+            Err(MathError::Overflow)
+        );
+        assert_eq!(mul_div(u128::MAX, 3, 3, Rounding::Up), Ok(u128::MAX));
+    }
+}
@@ -0,0 +1,174 @@
+//! Compares the library with the `calculate_pay_amount` copies it replaced.
+//! The copies are kept verbatim, lints and all.
+
+#![allow(clippy::identity_op, clippy::assign_op_pattern)]
+
+use pawn_interest::{Accrual, InterestConfig, LoanTerms, DAY_SECS};
+use proptest::prelude::*;
+
+/// near/nft-lend/src/sale.rs before the extraction
+fn legacy_near(
+    loan_principal_amount: u128,
+    loan_duration: u32,
+    interest_rate: u32,
+    loan_started_at: u128,
+    pay_at: u128,
+) -> u128 {
+    const DAY_SECS: u128 = 86400;
+    let mut max_loan_day: u128 = loan_duration as u128 / DAY_SECS;
+    if max_loan_day == 0 {
+        max_loan_day = 1;
+    }
+    let mut loan_day: u128 = max_loan_day;
+    if pay_at < loan_started_at + loan_duration as u128 && pay_at > loan_started_at {
+        loan_day = ((pay_at - loan_started_at) / DAY_SECS) + 1;
+    }
+    if loan_day >= max_loan_day {
+        loan_day = max_loan_day
+    }
+    let mut full_interst =
+        ((loan_principal_amount * (interest_rate as u128) / 10000) * loan_day) / 365;
+    if max_loan_day > loan_day {
+        full_interst = full_interst
+            + (((loan_principal_amount * (interest_rate as u128) / 10000)
+                * (max_loan_day - loan_day))
+                / 365)
+                / 2;
+    }
+    let fee = loan_principal_amount * 1 / 100;
+    fee + full_interst + loan_principal_amount
+}
+
+/// solana/nft-lend/program/src/utils.rs before the extraction
+fn legacy_solana(
+    loan_principal_amount: u64,
+    loan_duration: u64,
+    interest_rate: u64,
+    loan_started_at: u64,
+    pay_at: u64,
+) -> u64 {
+    let mut max_loan_day: u64 = loan_duration / DAY_SECS;
+    if max_loan_day == 0 {
+        max_loan_day = 1;
+    }
+    let mut loan_day: u64 = max_loan_day;
+    if pay_at < loan_started_at + loan_duration && pay_at > loan_started_at {
+        loan_day = ((pay_at - loan_started_at) / DAY_SECS) + 1;
+    }
+    if loan_day >= max_loan_day {
+        loan_day = max_loan_day
+    }
+    let mut full_interst = ((loan_principal_amount * interest_rate / 10000) * loan_day) / 365;
+    if max_loan_day > loan_day {
+        full_interst = full_interst
+            + (((loan_principal_amount * interest_rate / 10000) * (max_loan_day - loan_day)) / 365)
+                / 2;
+    }
+    let fee = loan_principal_amount * 1 / 100;
+    fee + full_interst + loan_principal_amount
+}
+
+/// solana/nft-lend/program/src/utils.rs `estimate_pay_amount` before the extraction
+#[allow(unused_assignments)]
+fn legacy_solana_estimate(
+    loan_principal_amount: u64,
+    loan_duration: u64,
+    interest_rate: u64,
+) -> u64 {
+    let mut result: u64 = 0;
+    result = ((loan_principal_amount * interest_rate / 10000) * (loan_duration / DAY_SECS)) / 365;
+    result + loan_principal_amount
+}
+
+#[test]
+fn whole_term_at_start() {
+    // a repayment in the second the loan started
+    let (principal, duration, rate, started_at) =
+        (1_000_000u64, 10 * DAY_SECS, 3650u64, 1_600_000_000u64);
+    let legacy = legacy_solana(principal, duration, rate, started_at, started_at);
+    let terms = LoanTerms {
+        principal: principal as u128,
+        duration,
+        interest_rate: rate as u32,
+        started_at,
+    };
+    let payoff = InterestConfig::default()
+        .payoff(&terms, started_at)
+        .unwrap();
+    // 1000 a day and a 1% fee, all 10 days are charged
+    assert_eq!(legacy, 1_000_000 + 10_000 + 10_000);
+    assert_eq!(payoff.total, legacy as u128);
+}
+
+proptest! {
+    #[test]
+    fn daily_matches_near(
+        principal in 0u128..1_000_000_000_000_000_000_000_000_000_000,
+        duration in 0u32..(3 * 365 * 86400),
+        rate in 0u32..=10000,
+        started_at in 1_600_000_000u64..1_700_000_000,
+        offset in 0u64..(4 * 365 * 86400),
+    ) {
+        let pay_at = started_at + offset;
+        let legacy = legacy_near(principal, duration, rate, started_at as u128, pay_at as u128);
+        let terms = LoanTerms { principal, duration: duration as u64, interest_rate: rate, started_at };
+        let total = InterestConfig::default().payoff(&terms, pay_at).unwrap().total;
+        prop_assert_eq!(total, legacy);
+    }
+
+    #[test]
+    fn daily_matches_solana(
+        principal in 0u64..100_000_000_000,
+        duration in 0u64..(3 * 365 * 86400),
+        rate in 0u64..=10000,
+        started_at in 1_600_000_000u64..1_700_000_000,
+        offset in 0u64..(4 * 365 * 86400),
+    ) {
+        let pay_at = started_at + offset;
+        let legacy = legacy_solana(principal, duration, rate, started_at, pay_at) as u128;
+        let terms = LoanTerms { principal: principal as u128, duration, interest_rate: rate as u32, started_at };
+        let total = InterestConfig::default().payoff(&terms, pay_at).unwrap().total;
+        prop_assert_eq!(total, legacy);
+    }
+
+    /// the estimate never charged a term under one day, utils.rs keeps that case
+    #[test]
+    fn maturity_matches_solana_estimate(
+        principal in 0u64..100_000_000_000,
+        duration in DAY_SECS..(3 * 365 * 86400),
+        rate in 0u64..=10000,
+    ) {
+        let legacy = legacy_solana_estimate(principal, duration, rate) as u128;
+        let terms = LoanTerms { principal: principal as u128, duration, interest_rate: rate as u32, started_at: 0 };
+        let amount = InterestConfig::default().amount_at_maturity(&terms).unwrap();
+        prop_assert_eq!(amount, legacy);
+    }
+
+    #[test]
+    fn fee_matches_legacy(principal in any::<u64>()) {
+        let fee = InterestConfig::default().fee(principal as u128).unwrap();
+        prop_assert_eq!(fee, (principal / 100) as u128);
+    }
+
+    #[test]
+    fn per_second_total_never_decreases(
+        principal in 0u128..1_000_000_000_000_000_000_000_000_000_000,
+        duration in 0u64..(3 * 365 * 86400),
+        rate in 0u32..=10000,
+        early_repayment_bps in 0u32..=10000,
+        first in 0u64..(4 * 365 * 86400),
+        step in 0u64..(365 * 86400),
+    ) {
+        let config = InterestConfig {
+            accrual: Accrual::PerSecond,
+            early_repayment_bps,
+            ..InterestConfig::default()
+        };
+        let terms = LoanTerms { principal, duration, interest_rate: rate, started_at: 0 };
+        let earlier = config.payoff(&terms, first).unwrap();
+        let later = config.payoff(&terms, first + step).unwrap();
+        // both parts round down separately, so allow one unit of slack
+        prop_assert!(later.total + 1 >= earlier.total);
+        prop_assert!(later.interest >= earlier.interest);
+    }
+}
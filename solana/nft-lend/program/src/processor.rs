@@ -160,7 +160,7 @@ impl Processor {
         loan_info.status = LoanStatus::Open as u8;
         loan_info.loan_start_at = 0;
         loan_info.pay_amount =
-            utils::estimate_pay_amount(loan_principal_amount, loan_duration, interest_rate);
+            utils::estimate_pay_amount(loan_principal_amount, loan_duration, interest_rate)?;
         msg!("done setup loan_info");
         Loan::pack(loan_info, &mut loan_info_account.try_borrow_mut_data()?)?;
         let (pda, _nonce) = Pubkey::find_program_address(&[b"lending"], program_id);
@@ -362,7 +362,7 @@ impl Processor {
 
         loan_info.loan_start_at = clock.unix_timestamp as u64;
         loan_info.pay_amount =
-            utils::estimate_pay_amount(loan_principal_amount, loan_duration, interest_rate);
+            utils::estimate_pay_amount(loan_principal_amount, loan_duration, interest_rate)?;
         loan_info.status = LoanStatus::Processing as u8; //processing
         loan_info.lender_pubkey = *lender.key;
         loan_info.offer_id = *offer_info_account.key;
@@ -751,7 +751,7 @@ impl Processor {
             loan_info.interest_rate,
             loan_info.loan_start_at,
             clock.unix_timestamp as u64,
-        );
+        )?;
         msg!("input real_pay_amount: {}", real_pay_amount);
         // if pay_amount != real_pay_amount {
         //     return Err(LendingError::ExpectedAmountMismatch.into());
@@ -765,7 +765,7 @@ impl Processor {
         }
         //repay
         //1% fee
-        let fee: u64 = utils::calculate_fee(loan_info.loan_principal_amount);
+        let fee: u64 = utils::calculate_fee(loan_info.loan_principal_amount)?;
 
         //transfer token to lender
         let transfer_token_to_lender_ix = spl_token::instruction::transfer(
@@ -1073,7 +1073,7 @@ impl Processor {
             loan_info.loan_principal_amount,
             loan_info.loan_duration,
             loan_info.interest_rate,
-        );
+        )?;
         loan_info.status = LoanStatus::Processing as u8; //processing
         loan_info.lender_pubkey = *lender.key;
         loan_info.offer_id = *offer_info_account.key;
@@ -1,17 +1,37 @@
-use solana_program::msg;
-pub const DAY_SECS: u64 = 86400;
+use crate::error::LendingError;
+use pawn_interest::{InterestConfig, LoanTerms};
+use solana_program::{msg, program_error::ProgramError};
+pub const DAY_SECS: u64 = pawn_interest::DAY_SECS;
+
+fn to_u64(amount: u128) -> Result<u64, ProgramError> {
+    u64::try_from(amount).map_err(|_| LendingError::AmountOverflow.into())
+}
+
 pub fn estimate_pay_amount(
     loan_principal_amount: u64,
     loan_duration: u64,
     interest_rate: u64,
-) -> u64 {
-    let mut result: u64 = 0;
-    result = ((loan_principal_amount * interest_rate / 10000) * (loan_duration / DAY_SECS)) / 365;
-    result + loan_principal_amount
+) -> Result<u64, ProgramError> {
+    // the estimate has always counted whole days only, a term under one day adds no interest
+    if loan_duration < DAY_SECS {
+        return Ok(loan_principal_amount);
+    }
+    let terms = LoanTerms {
+        principal: loan_principal_amount as u128,
+        duration: loan_duration,
+        interest_rate: u32::try_from(interest_rate).map_err(|_| LendingError::AmountOverflow)?,
+        started_at: 0,
+    };
+    let amount = InterestConfig::default()
+        .amount_at_maturity(&terms)
+        .map_err(|_| LendingError::AmountOverflow)?;
+    to_u64(amount)
 }
-pub fn calculate_fee(loan_principal_amount: u64) -> u64 {
-    let fee = loan_principal_amount * 1 / 100;
-    fee
+pub fn calculate_fee(loan_principal_amount: u64) -> Result<u64, ProgramError> {
+    let fee = InterestConfig::default()
+        .fee(loan_principal_amount as u128)
+        .map_err(|_| LendingError::AmountOverflow)?;
+    to_u64(fee)
 }
 
 pub fn calculate_pay_amount(
@@ -20,37 +40,25 @@ pub fn calculate_pay_amount(
     interest_rate: u64,
     loan_started_at: u64,
     pay_at: u64,
-) -> u64 {
-    //1%(principla) + 100% interest to pay_at + 50% interest for the rest
-    let mut max_loan_day: u64 = loan_duration / DAY_SECS;
-    if max_loan_day == 0 {
-        max_loan_day = 1;
-    }
-    let mut loan_day: u64 = max_loan_day;
-    if pay_at < loan_started_at + loan_duration && pay_at > loan_started_at {
-        loan_day = ((pay_at - loan_started_at) / DAY_SECS) + 1;
-    }
-    if loan_day >= max_loan_day {
-        loan_day = max_loan_day
-    }
-    //100% interest loan day
-    let mut full_interst = ((loan_principal_amount * interest_rate / 10000) * loan_day) / 365;
-    if max_loan_day > loan_day {
-        //50% interest remain day
-        full_interst = full_interst
-            + (((loan_principal_amount * interest_rate / 10000) * (max_loan_day - loan_day)) / 365)
-                / 2;
-    }
-    //1% fee (base on principal amount)
-    let fee = calculate_fee(loan_principal_amount);
+) -> Result<u64, ProgramError> {
+    //1%(principla) + 100% interest to pay_at + 50% interest for the rest, see pawn-interest
+    let terms = LoanTerms {
+        principal: loan_principal_amount as u128,
+        duration: loan_duration,
+        interest_rate: u32::try_from(interest_rate).map_err(|_| LendingError::AmountOverflow)?,
+        started_at: loan_started_at,
+    };
+    let payoff = InterestConfig::default()
+        .payoff(&terms, pay_at)
+        .map_err(|_| LendingError::AmountOverflow)?;
     msg!(
-        "loan_principal_amount: {}, loan_duration: {},  interest_rate: {},  loan_day: {}, interest: {}, fee : {}",
+        "loan_principal_amount: {}, loan_duration: {},  interest_rate: {},  interest: {}, discounted_interest: {}, fee : {}",
         loan_principal_amount,
         loan_duration,
         interest_rate,
-        loan_day,
-        full_interst,
-        fee,
+        payoff.interest,
+        payoff.discounted_interest,
+        payoff.fee,
     );
-    fee + full_interst + loan_principal_amount
+    to_u64(payoff.total)
 }